@@ -0,0 +1,32 @@
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use kraken::Amount;
+use kraken::reader::parse_amount;
+use std::hint::black_box;
+
+const AMOUNTS: [&[u8]; 6] = [b"1", b"100.0", b"0.1234", b"2742.5", b"123456.789", b"922337203685477.5807"];
+
+fn amount_parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("amount");
+    group.throughput(Throughput::Elements(AMOUNTS.len() as u64));
+    group.bench_function("from_utf8_trim_from_str", |b| {
+        b.iter(|| {
+            for raw in AMOUNTS {
+                let parsed = std::str::from_utf8(black_box(raw))
+                    .ok()
+                    .and_then(|raw| raw.trim().parse::<Amount>().ok());
+                black_box(parsed);
+            }
+        })
+    });
+    group.bench_function("parse_amount", |b| {
+        b.iter(|| {
+            for raw in AMOUNTS {
+                black_box(parse_amount(black_box(raw)).ok());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, amount_parsing);
+criterion_main!(benches);
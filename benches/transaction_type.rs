@@ -0,0 +1,58 @@
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use kraken::error::Error;
+use kraken::reader::{TransactionType, parse_transaction_type};
+use std::hint::black_box;
+
+const TYPES: [&[u8]; 10] = [
+    b"deposit",
+    b"withdrawal",
+    b"dispute",
+    b"resolve",
+    b"chargeback",
+    b"freeze",
+    b"unfreeze",
+    b"close",
+    b"transfer",
+    b"adjust",
+];
+
+/// The plain match over every name the first-byte dispatch replaced.
+fn match_all(raw: &[u8], line_number: u64) -> Result<TransactionType, Error> {
+    match raw.trim_ascii() {
+        b"deposit" => Ok(TransactionType::Deposit),
+        b"withdrawal" => Ok(TransactionType::Withdrawal),
+        b"dispute" => Ok(TransactionType::Dispute),
+        b"resolve" => Ok(TransactionType::Resolve),
+        b"chargeback" => Ok(TransactionType::Chargeback),
+        b"freeze" => Ok(TransactionType::Freeze),
+        b"unfreeze" => Ok(TransactionType::Unfreeze),
+        b"close" => Ok(TransactionType::Close),
+        b"transfer" => Ok(TransactionType::Transfer),
+        b"adjust" => Ok(TransactionType::Adjust),
+        b"" => Err(Error::MissingTransactionType(line_number)),
+        _ => Err(Error::UnknownTransactionType(line_number)),
+    }
+}
+
+fn transaction_type_parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transaction_type");
+    group.throughput(Throughput::Elements(TYPES.len() as u64));
+    group.bench_function("match_all", |b| {
+        b.iter(|| {
+            for raw in TYPES {
+                black_box(match_all(black_box(raw), 1).ok());
+            }
+        })
+    });
+    group.bench_function("parse_transaction_type", |b| {
+        b.iter(|| {
+            for raw in TYPES {
+                black_box(parse_transaction_type(black_box(raw), 1).ok());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, transaction_type_parsing);
+criterion_main!(benches);
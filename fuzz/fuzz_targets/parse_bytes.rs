@@ -0,0 +1,16 @@
+#![no_main]
+
+use kraken::reader::parse_bytes;
+use kraken::settings::{BufferSettings, Settings};
+use kraken::writer::write_accounts;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let settings = Settings {
+        buffer: BufferSettings { capacity: 8192 },
+        ..Default::default()
+    };
+    if let Ok(accounts) = parse_bytes(data, &settings) {
+        let _ = write_accounts(accounts);
+    }
+});
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
-use primitive_fixed_point_decimal::ConstScaleFpdec;
-use crate::Amount;
+use crate::money::Money;
+use crate::{Amount, ClientId};
 
 #[derive(thiserror::Error, Debug)]
 pub enum AccountError {
@@ -8,86 +8,445 @@ pub enum AccountError {
     NoTransaction(u64),
     #[error("Dispute not found for resolve/chargeback of transaction id {0}")]
     NoDispute(u64),
+    #[error("Available funds do not cover the dispute of transaction id {0}")]
+    InsufficientAvailableForDispute(u64),
+    #[error("Balance overflow applying transaction id {0}")]
+    Overflow(u64),
+    #[error("Available funds do not cover the transfer of transaction id {0}")]
+    InsufficientFunds(u64),
+    #[error("Account is locked, chargeback of transaction id {0} rejected")]
+    AccountLocked(u64),
+    #[error("Client {0} cannot be closed while funds are held")]
+    CannotCloseWithHeldFunds(ClientId),
+    #[error("Transaction id {0} is under dispute and can't be adjusted")]
+    AdjustDisputed(u64),
 }
 
 pub type AccountResult<T> = Result<T, AccountError>;
 
-#[derive(Default)]
-pub struct Account {
-    pub client: u16,
-    pub funds_available: ConstScaleFpdec<i64, 4>,
-    pub funds_held: ConstScaleFpdec<i64, 4>,
-    disputes: HashMap<u64, Amount>,
-    disputable_transactions: HashMap<u64, Amount>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    Active,
+    Frozen,
+    Closed,
+}
+
+impl AccountStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AccountStatus::Active => "active",
+            AccountStatus::Frozen => "frozen",
+            AccountStatus::Closed => "closed",
+        }
+    }
+
+    /// Whether the account accepts no further transactions in this status.
+    pub fn is_terminal(self) -> bool {
+        matches!(self, AccountStatus::Frozen | AccountStatus::Closed)
+    }
+}
+
+/// Whether a disputable transaction added or removed funds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Deposit,
+    Withdrawal,
+}
+
+/// How a dispute is handled when the available funds don't cover the disputed amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputePolicy {
+    /// Reject the dispute
+    Strict,
+    /// Hold the full amount, letting the available funds go negative
+    #[default]
+    AllowNegative,
+    /// Hold only what is available
+    Clamp,
+}
+
+/// A deposit or withdrawal that can still be disputed, or is under dispute.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+struct Movement<M> {
+    direction: Direction,
+    amount: M,
+}
+
+/// A disputed transaction and the part of its amount actually held.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+struct OpenDispute<M> {
+    movement: Movement<M>,
+    held: M,
+    /// Record index at which the dispute was opened; snapshots predating it restore as 0
+    #[serde(default)]
+    held_since: u64,
+}
+
+/// Balances and dispute state of a client, generic over the amount type for wider backends.
+#[derive(Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Account<M = Amount> {
+    pub client: ClientId,
+    pub funds_available: M,
+    pub funds_held: M,
+    /// Held funds carried over from seeded opening balances, which belong to no dispute
+    #[serde(default)]
+    opening_held: M,
+    disputes: HashMap<u64, OpenDispute<M>>,
+    /// Keyed by transaction id; a deposit and a withdrawal sharing an id keep only the later one
+    disputable_transactions: HashMap<u64, Movement<M>>,
     pub locked: bool,
+    closed: bool,
+    transaction_count: u64,
 }
 
 impl Account {
-    pub(crate) fn new(client: u16) -> Self {
+    pub fn new(client: ClientId) -> Self {
+        Account::with_client(client)
+    }
+
+    /// Restores an account from `to_snapshot` output.
+    pub fn from_snapshot(snapshot: &str) -> crate::prelude::Result<Self> {
+        Ok(serde_json::from_str(snapshot)?)
+    }
+
+    /// Share of the total held, `held / (held + available)`, or `None` when the total is zero
+    /// or below so there is no meaningful ratio.
+    pub fn hold_ratio(&self) -> Option<f64> {
+        let total = self.funds_held.checked_add(self.funds_available)?;
+        (total.mantissa() > 0).then(|| self.funds_held.mantissa() as f64 / total.mantissa() as f64)
+    }
+}
+
+impl<M: Money> Account<M> {
+    /// An empty account of `client` with any amount type; `new` picks `Amount`.
+    pub(crate) fn with_client(client: ClientId) -> Self {
         Account {
             client,
             ..Default::default()
         }
     }
 
+    /// Funds available for withdrawal, same as `funds_available`.
+    pub fn available(&self) -> M {
+        self.funds_available
+    }
+
+    /// Funds held by open disputes, same as `funds_held`.
+    pub fn held(&self) -> M {
+        self.funds_held
+    }
+
+    /// Whether a chargeback or `freeze` locked the account, same as `locked`.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn status(&self) -> AccountStatus {
+        if self.closed {
+            AccountStatus::Closed
+        } else if self.locked {
+            AccountStatus::Frozen
+        } else {
+            AccountStatus::Active
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        self.status().is_terminal()
+    }
+
+    /// Serializes the complete state, including open disputes, as JSON.
+    pub fn to_snapshot(&self) -> crate::prelude::Result<String>
+    where
+        M: serde::Serialize,
+    {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Whether the transaction is currently under dispute.
+    pub fn is_disputed(&self, transaction_id: u64) -> bool {
+        self.disputes.contains_key(&transaction_id)
+    }
+
+    /// Direction of the latest deposit or withdrawal with this id that is disputable or under dispute.
+    pub fn direction(&self, transaction_id: u64) -> Option<Direction> {
+        self.disputable_transactions
+            .get(&transaction_id)
+            .or_else(|| self.disputes.get(&transaction_id).map(|dispute| &dispute.movement))
+            .map(|movement| movement.direction)
+    }
+
+    /// Whether `funds_held` equals the sum held by the open disputes plus the opening held
+    /// funds, as it has to since only disputes hold funds after seeding.
+    pub fn held_matches_disputes(&self) -> bool {
+        self.disputes
+            .values()
+            .try_fold(self.opening_held, |sum, dispute| sum.checked_add(dispute.held))
+            .is_some_and(|disputed| disputed == self.funds_held)
+    }
+
+    /// Marks `held` of the held funds as carried over from an opening balance rather than
+    /// held by a dispute.
+    pub(crate) fn set_opening_held(&mut self, held: M) {
+        self.opening_held = held;
+    }
+
+    /// Number of disputes currently open on this account.
+    pub fn disputed_count(&self) -> usize {
+        self.disputes.len()
+    }
+
+    /// Open disputes as transaction id and the amount held for it, in no particular order.
+    pub fn open_disputes(&self) -> impl Iterator<Item = (u64, M)> + '_ {
+        self.disputes.iter().map(|(&transaction_id, dispute)| (transaction_id, dispute.held))
+    }
+
+    /// Record index at which the open dispute of the transaction was opened. There is no real
+    /// time, so how long a dispute has been open is measured in records processed since then.
+    pub fn held_since(&self, transaction_id: u64) -> Option<u64> {
+        self.disputes.get(&transaction_id).map(|dispute| dispute.held_since)
+    }
+
+    /// Number of transactions successfully applied to this account.
+    pub fn transaction_count(&self) -> u64 {
+        self.transaction_count
+    }
+
     pub(crate) fn withdraw(
         &mut self,
         transaction_id: u64,
-        amount: Amount,
-    ) {
-        self.funds_available -= amount;
+        amount: M,
+    ) -> AccountResult<()> {
+        self.funds_available = checked(self.funds_available.checked_sub(amount), transaction_id)?;
         self.disputable_transactions
-            .insert(transaction_id, amount);
+            .insert(transaction_id, Movement { direction: Direction::Withdrawal, amount });
+        self.transaction_count += 1;
+        Ok(())
     }
 
     pub(crate) fn deposit(
         &mut self,
         transaction_id: u64,
-        amount: Amount,
-    ) {
-        self.funds_available += amount;
+        amount: M,
+    ) -> AccountResult<()> {
+        self.funds_available = checked(self.funds_available.checked_add(amount), transaction_id)?;
         self.disputable_transactions
-            .insert(transaction_id, amount);
+            .insert(transaction_id, Movement { direction: Direction::Deposit, amount });
+        self.transaction_count += 1;
+        Ok(())
+    }
+
+    /// Like `withdraw`, clamping the available funds to `M::MIN` instead of failing on
+    /// overflow. Returns the amount actually debited, which is all that can be disputed.
+    pub(crate) fn withdraw_saturating(&mut self, transaction_id: u64, amount: M) -> AccountResult<M> {
+        let amount = match self.funds_available.checked_sub(amount) {
+            Some(_) => amount,
+            None => checked(self.funds_available.checked_sub(M::MIN), transaction_id)?,
+        };
+        self.withdraw(transaction_id, amount)?;
+        Ok(amount)
+    }
+
+    /// Like `deposit`, clamping the available funds to `M::MAX` instead of failing on
+    /// overflow. Returns the amount actually credited, which is all that can be disputed.
+    pub(crate) fn deposit_saturating(&mut self, transaction_id: u64, amount: M) -> AccountResult<M> {
+        let amount = match self.funds_available.checked_add(amount) {
+            Some(_) => amount,
+            None => checked(M::MAX.checked_sub(self.funds_available), transaction_id)?,
+        };
+        self.deposit(transaction_id, amount)?;
+        Ok(amount)
+    }
+
+    /// Moves funds out to another client. Unlike a withdrawal, a transfer can't go below
+    /// zero available funds and can't be disputed.
+    pub(crate) fn transfer_out(&mut self, transaction_id: u64, amount: M) -> AccountResult<()> {
+        if self.funds_available < amount {
+            return Err(AccountError::InsufficientFunds(transaction_id));
+        }
+        self.funds_available = checked(self.funds_available.checked_sub(amount), transaction_id)?;
+        self.transaction_count += 1;
+        Ok(())
+    }
+
+    /// Deducts a fee from the available funds. Returns `false`, leaving the account unchanged,
+    /// when the available funds don't cover it.
+    pub(crate) fn charge_fee(&mut self, amount: M) -> bool {
+        match self.funds_available.checked_sub(amount) {
+            Some(available) if available >= M::ZERO => {
+                self.funds_available = available;
+                self.transaction_count += 1;
+                true
+            }
+            _ => false,
+        }
     }
 
+    /// Receives funds transferred from another client.
+    pub(crate) fn transfer_in(&mut self, transaction_id: u64, amount: M) -> AccountResult<()> {
+        self.funds_available = checked(self.funds_available.checked_add(amount), transaction_id)?;
+        self.transaction_count += 1;
+        Ok(())
+    }
+
+    /// Returns the held amount of an open dispute to the available funds. A disputed withdrawal
+    /// holds nothing, so resolving it leaves the balances as they are.
+    ///
+    /// Funds are only returned while the transaction has an entry in `disputes`, and that entry
+    /// is removed together with the balance update. A repeated resolve therefore fails with
+    /// `NoDispute` and returns nothing, however leniently the caller treats that error.
     pub(crate) fn resolve(&mut self, transaction_id: u64) -> AccountResult<()> {
-        let disputed_amount = self
+        let disputed = *self
             .disputes
-            .remove(&transaction_id)
+            .get(&transaction_id)
             .ok_or(AccountError::NoDispute(transaction_id))?;
-        self.funds_available += disputed_amount;
-        self.funds_held -= disputed_amount;
+        let disputed_amount = disputed.held;
+        let available = checked(self.funds_available.checked_add(disputed_amount), transaction_id)?;
+        let held = checked(self.funds_held.checked_sub(disputed_amount), transaction_id)?;
+        self.disputes.remove(&transaction_id);
+        self.funds_available = available;
+        self.funds_held = held;
         self.disputable_transactions
-            .insert(transaction_id, disputed_amount);
+            .insert(transaction_id, disputed.movement);
+        self.transaction_count += 1;
         Ok(())
     }
 
-    pub(crate) fn chargeback(&mut self, transaction_id: u64) -> AccountResult<()> {
-        let disputed_amount = self
+    /// Reverses a disputed transaction and locks the account: a deposit's held amount is
+    /// removed, a withdrawal's amount is paid back to the available funds. Returns the change
+    /// of the account's total, negative when the funds went down. A locked account accepts no
+    /// further chargebacks, even for disputes opened after it was locked.
+    pub(crate) fn chargeback(&mut self, transaction_id: u64) -> AccountResult<M> {
+        if self.locked {
+            return Err(AccountError::AccountLocked(transaction_id));
+        }
+        let disputed = *self
             .disputes
-            .remove(&transaction_id)
+            .get(&transaction_id)
             .ok_or(AccountError::NoDispute(transaction_id))?;
-        self.funds_held -= disputed_amount;
+        let held = checked(self.funds_held.checked_sub(disputed.held), transaction_id)?;
+        let (available, delta) = match disputed.movement.direction {
+            Direction::Deposit => (self.funds_available, checked(M::ZERO.checked_sub(disputed.held), transaction_id)?),
+            Direction::Withdrawal => {
+                let amount = disputed.movement.amount;
+                (checked(self.funds_available.checked_add(amount), transaction_id)?, amount)
+            }
+        };
+        self.disputes.remove(&transaction_id);
+        self.funds_available = available;
+        self.funds_held = held;
+        self.locked = true;
+        self.transaction_count += 1;
+        Ok(delta)
+    }
+
+    /// Administratively locks the account without touching its balances.
+    pub(crate) fn freeze(&mut self) {
         self.locked = true;
-        // assume no more disputes possible on that account
+        self.transaction_count += 1;
+    }
+
+    /// Lifts a lock, whether it came from `freeze` or a chargeback.
+    pub(crate) fn unfreeze(&mut self) {
+        self.locked = false;
+        self.transaction_count += 1;
+    }
+
+    /// Finalizes the account; only possible once no funds are held by open disputes.
+    pub(crate) fn close(&mut self) -> AccountResult<()> {
+        if !self.funds_held.is_zero() {
+            return Err(AccountError::CannotCloseWithHeldFunds(self.client));
+        }
+        self.closed = true;
+        self.transaction_count += 1;
         Ok(())
     }
 
+    /// Corrects the amount of a deposit or withdrawal that isn't under dispute to `amount`,
+    /// changing the available funds by the difference. Returns that change, negative when
+    /// the funds went down. Like a transfer, an adjustment lowering the funds can't take them
+    /// below zero.
+    pub(crate) fn adjust(&mut self, transaction_id: u64, amount: M) -> AccountResult<M> {
+        if self.disputes.contains_key(&transaction_id) {
+            return Err(AccountError::AdjustDisputed(transaction_id));
+        }
+        let movement = self
+            .disputable_transactions
+            .get_mut(&transaction_id)
+            .ok_or(AccountError::NoTransaction(transaction_id))?;
+        let (available, delta) = match movement.direction {
+            Direction::Deposit => {
+                let delta = checked(amount.checked_sub(movement.amount), transaction_id)?;
+                (checked(self.funds_available.checked_add(delta), transaction_id)?, delta)
+            }
+            Direction::Withdrawal => {
+                let delta = checked(movement.amount.checked_sub(amount), transaction_id)?;
+                (checked(self.funds_available.checked_add(delta), transaction_id)?, delta)
+            }
+        };
+        if delta < M::ZERO && available < M::ZERO {
+            return Err(AccountError::InsufficientFunds(transaction_id));
+        }
+        movement.amount = amount;
+        self.funds_available = available;
+        self.transaction_count += 1;
+        Ok(delta)
+    }
+
+    /// Disputes a transaction with the default policy.
+    #[cfg(test)]
     pub(crate) fn dispute(&mut self, transaction_id: u64) -> AccountResult<()> {
-        let disputed_amount = self
+        self.dispute_with(transaction_id, DisputePolicy::default())
+    }
+
+    /// Like `dispute`, with `policy` deciding what happens when the available funds don't cover
+    /// the disputed amount.
+    #[cfg(test)]
+    pub(crate) fn dispute_with(&mut self, transaction_id: u64, policy: DisputePolicy) -> AccountResult<()> {
+        self.dispute_at(transaction_id, policy, 0)
+    }
+
+    /// Moves the amount of a deposit from available to held funds, with `policy` deciding
+    /// what happens when the available funds don't cover it. A withdrawal's funds already left
+    /// the account, so disputing one holds nothing until a chargeback pays it back.
+    /// `record` is the index of the dispute record, kept as `held_since`.
+    pub(crate) fn dispute_at(&mut self, transaction_id: u64, policy: DisputePolicy, record: u64) -> AccountResult<()> {
+        let disputed = *self
             .disputable_transactions
-            .remove(&transaction_id)
+            .get(&transaction_id)
             .ok_or(AccountError::NoTransaction(transaction_id))?;
-        self.funds_available -= disputed_amount;
-        self.funds_held += disputed_amount;
-        self.disputes.insert(transaction_id, disputed_amount);
+        let disputed_amount = match (disputed.direction, policy) {
+            (Direction::Withdrawal, _) => M::ZERO,
+            (_, DisputePolicy::Strict) if self.funds_available < disputed.amount => {
+                return Err(AccountError::InsufficientAvailableForDispute(transaction_id));
+            }
+            (_, DisputePolicy::Clamp) => disputed.amount.min(self.funds_available.max(M::ZERO)),
+            _ => disputed.amount,
+        };
+        let available = checked(self.funds_available.checked_sub(disputed_amount), transaction_id)?;
+        let held = checked(self.funds_held.checked_add(disputed_amount), transaction_id)?;
+        self.disputable_transactions.remove(&transaction_id);
+        self.funds_available = available;
+        self.funds_held = held;
+        self.disputes.insert(transaction_id, OpenDispute { movement: disputed, held: disputed_amount, held_since: record });
+        self.transaction_count += 1;
         Ok(())
     }
 }
 
+/// Balances are only updated once every step of a transaction has succeeded,
+/// so an overflow leaves the account exactly as it was.
+fn checked<M>(value: Option<M>, transaction_id: u64) -> AccountResult<M> {
+    value.ok_or(AccountError::Overflow(transaction_id))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
 
     fn create_amount(value: &str) -> Amount {
         value.parse().expect("Failed to parse amount")
@@ -98,9 +457,30 @@ mod tests {
         let account = Account::new(42);
 
         assert_eq!(account.client, 42);
-        assert_eq!(account.funds_available.to_string(), "0");
-        assert_eq!(account.funds_held.to_string(), "0");
+        assert!(account.funds_available.is_zero());
+        assert!(account.funds_held.is_zero());
         assert!(!account.locked);
+        assert_eq!(account.status(), AccountStatus::Active);
+        assert!(!account.is_terminal());
+    }
+
+    #[test]
+    fn test_is_zero_balances() {
+        let mut account = Account::new(1);
+        assert!(account.funds_available.is_zero());
+        assert_eq!(account.funds_available, Amount::ZERO);
+
+        // Positive balance
+        account.deposit(1, create_amount("10.0")).expect("Deposit should succeed");
+        assert!(!account.funds_available.is_zero());
+
+        // Negative balance
+        account.withdraw(2, create_amount("25.0")).expect("Withdrawal should succeed");
+        assert!(!account.funds_available.is_zero());
+
+        // Back to zero
+        account.deposit(3, create_amount("15.0")).expect("Deposit should succeed");
+        assert!(account.funds_available.is_zero());
     }
 
     #[test]
@@ -108,41 +488,41 @@ mod tests {
         let mut account = Account::new(1);
         let amount = create_amount("100.50");
 
-        account.deposit(1, amount);
+        account.deposit(1, amount).expect("Deposit should succeed");
 
         assert_eq!(account.funds_available.to_string(), "100.5");
-        assert_eq!(account.funds_held.to_string(), "0");
+        assert!(account.funds_held.is_zero());
     }
 
     #[test]
     fn test_multiple_deposits() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("100.0"));
-        account.deposit(2, create_amount("50.25"));
-        account.deposit(3, create_amount("25.75"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.deposit(2, create_amount("50.25")).expect("Deposit should succeed");
+        account.deposit(3, create_amount("25.75")).expect("Deposit should succeed");
 
         assert_eq!(account.funds_available.to_string(), "176");
-        assert_eq!(account.funds_held.to_string(), "0");
+        assert!(account.funds_held.is_zero());
     }
 
     #[test]
     fn test_withdrawal() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("100.0"));
-        account.withdraw(2, create_amount("30.0"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.withdraw(2, create_amount("30.0")).expect("Withdrawal should succeed");
 
         assert_eq!(account.funds_available.to_string(), "70");
-        assert_eq!(account.funds_held.to_string(), "0");
+        assert!(account.funds_held.is_zero());
     }
 
     #[test]
     fn test_withdrawal_can_go_negative() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("50.0"));
-        account.withdraw(2, create_amount("75.0"));
+        account.deposit(1, create_amount("50.0")).expect("Deposit should succeed");
+        account.withdraw(2, create_amount("75.0")).expect("Withdrawal should succeed");
 
         // No check for sufficient funds, so balance can go negative
         assert_eq!(account.funds_available.to_string(), "-25");
@@ -152,11 +532,11 @@ mod tests {
     fn test_dispute_moves_funds_to_held() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("100.0"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
         let result = account.dispute(1);
 
         assert!(result.is_ok());
-        assert_eq!(account.funds_available.to_string(), "0");
+        assert!(account.funds_available.is_zero());
         assert_eq!(account.funds_held.to_string(), "100");
         assert!(!account.locked);
     }
@@ -165,40 +545,72 @@ mod tests {
     fn test_dispute_nonexistent_transaction() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("100.0"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
         let result = account.dispute(999);
 
         assert!(matches!(result, Err(AccountError::NoTransaction(999))));
         // Funds should remain unchanged
         assert_eq!(account.funds_available.to_string(), "100");
-        assert_eq!(account.funds_held.to_string(), "0");
+        assert!(account.funds_held.is_zero());
+    }
+
+    #[test]
+    fn test_dispute_uncovered_allowed_by_default() {
+        let mut account = Account::new(1);
+
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.withdraw(2, create_amount("80.0")).expect("Withdrawal should succeed");
+        let result = account.dispute(1);
+
+        assert!(result.is_ok());
+        assert_eq!(account.funds_available.to_string(), "-80");
+        assert_eq!(account.funds_held.to_string(), "100");
+    }
+
+    #[test]
+    fn test_dispute_requires_available() {
+        let mut account = Account::new(1);
+
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.withdraw(2, create_amount("80.0")).expect("Withdrawal should succeed");
+        let result = account.dispute_with(1, DisputePolicy::Strict);
+
+        assert!(matches!(result, Err(AccountError::InsufficientAvailableForDispute(1))));
+        assert_eq!(account.funds_available.to_string(), "20");
+        assert!(account.funds_held.is_zero());
+
+        // The transaction stays disputable once the funds cover it again
+        account.deposit(3, create_amount("80.0")).expect("Deposit should succeed");
+        assert!(account.dispute_with(1, DisputePolicy::Strict).is_ok());
+        assert_eq!(account.funds_held.to_string(), "100");
     }
 
     #[test]
     fn test_dispute_withdrawal() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("100.0"));
-        account.withdraw(2, create_amount("30.0"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.withdraw(2, create_amount("30.0")).expect("Withdrawal should succeed");
         let result = account.dispute(2);
 
         assert!(result.is_ok());
-        // Disputing a withdrawal: available 70 - 30 = 40, held = 30
-        assert_eq!(account.funds_available.to_string(), "40");
-        assert_eq!(account.funds_held.to_string(), "30");
+        // Disputing a withdrawal: its 30 already left, so available stays 70 and nothing is held
+        assert_eq!(account.funds_available.to_string(), "70");
+        assert!(account.funds_held.is_zero());
+        assert!(account.is_disputed(2));
     }
 
     #[test]
     fn test_resolve_returns_funds_to_available() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("100.0"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
         account.dispute(1).expect("Dispute should succeed");
         let result = account.resolve(1);
 
         assert!(result.is_ok());
         assert_eq!(account.funds_available.to_string(), "100");
-        assert_eq!(account.funds_held.to_string(), "0");
+        assert!(account.funds_held.is_zero());
         assert!(!account.locked);
     }
 
@@ -206,26 +618,42 @@ mod tests {
     fn test_resolve_nonexistent_dispute() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("100.0"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
         let result = account.resolve(1);
 
         assert!(matches!(result, Err(AccountError::NoDispute(1))));
         assert_eq!(account.funds_available.to_string(), "100");
-        assert_eq!(account.funds_held.to_string(), "0");
+        assert!(account.funds_held.is_zero());
+    }
+
+    #[test]
+    fn test_repeated_resolve_returns_funds_once() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("10.0")).expect("Deposit should succeed");
+        account.deposit(2, create_amount("5.0")).expect("Deposit should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+        account.resolve(1).expect("Resolve should succeed");
+
+        let result = account.resolve(1);
+
+        assert!(matches!(result, Err(AccountError::NoDispute(1))));
+        assert_eq!(account.funds_available.to_string(), "15");
+        assert!(account.funds_held.is_zero());
+        assert_eq!(account.transaction_count(), 4);
     }
 
     #[test]
     fn test_resolve_makes_transaction_disputable_again() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("100.0"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
         account.dispute(1).expect("First dispute should succeed");
         account.resolve(1).expect("Resolve should succeed");
 
         // After resolve, transaction should be disputable again
         let result = account.dispute(1);
         assert!(result.is_ok());
-        assert_eq!(account.funds_available.to_string(), "0");
+        assert!(account.funds_available.is_zero());
         assert_eq!(account.funds_held.to_string(), "100");
     }
 
@@ -233,21 +661,23 @@ mod tests {
     fn test_chargeback_locks_account() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("100.0"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
         account.dispute(1).expect("Dispute should succeed");
         let result = account.chargeback(1);
 
         assert!(result.is_ok());
-        assert_eq!(account.funds_available.to_string(), "0");
-        assert_eq!(account.funds_held.to_string(), "0");
+        assert!(account.funds_available.is_zero());
+        assert!(account.funds_held.is_zero());
         assert!(account.locked);
+        assert_eq!(account.status(), AccountStatus::Frozen);
+        assert!(account.is_terminal());
     }
 
     #[test]
     fn test_chargeback_nonexistent_dispute() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("100.0"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
         let result = account.chargeback(1);
 
         assert!(matches!(result, Err(AccountError::NoDispute(1))));
@@ -258,8 +688,8 @@ mod tests {
     fn test_chargeback_removes_held_funds() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("200.0"));
-        account.deposit(2, create_amount("100.0"));
+        account.deposit(1, create_amount("200.0")).expect("Deposit should succeed");
+        account.deposit(2, create_amount("100.0")).expect("Deposit should succeed");
         account.dispute(1).expect("Dispute should succeed");
 
         // Before chargeback: available = 100, held = 200
@@ -270,7 +700,7 @@ mod tests {
 
         // After chargeback: available = 100, held = 0 (200 was charged back)
         assert_eq!(account.funds_available.to_string(), "100");
-        assert_eq!(account.funds_held.to_string(), "0");
+        assert!(account.funds_held.is_zero());
         assert!(account.locked);
     }
 
@@ -279,12 +709,12 @@ mod tests {
         let mut account = Account::new(1);
 
         // Multiple deposits
-        account.deposit(1, create_amount("100.0"));
-        account.deposit(2, create_amount("50.0"));
-        account.deposit(3, create_amount("25.0"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.deposit(2, create_amount("50.0")).expect("Deposit should succeed");
+        account.deposit(3, create_amount("25.0")).expect("Deposit should succeed");
 
         // Withdrawal
-        account.withdraw(4, create_amount("30.0"));
+        account.withdraw(4, create_amount("30.0")).expect("Withdrawal should succeed");
 
         // Total: 100 + 50 + 25 - 30 = 145
         assert_eq!(account.funds_available.to_string(), "145");
@@ -297,17 +727,17 @@ mod tests {
         // Resolve the dispute
         account.resolve(2).expect("Resolve should succeed");
         assert_eq!(account.funds_available.to_string(), "145");
-        assert_eq!(account.funds_held.to_string(), "0");
+        assert!(account.funds_held.is_zero());
 
-        // Dispute withdrawal of 30
+        // Dispute withdrawal of 30, whose funds already left the account
         account.dispute(4).expect("Dispute withdrawal should succeed");
-        assert_eq!(account.funds_available.to_string(), "115");
-        assert_eq!(account.funds_held.to_string(), "30");
+        assert_eq!(account.funds_available.to_string(), "145");
+        assert!(account.funds_held.is_zero());
 
-        // Chargeback the withdrawal dispute
+        // Chargeback the withdrawal dispute, paying the withdrawal back
         account.chargeback(4).expect("Chargeback should succeed");
-        assert_eq!(account.funds_available.to_string(), "115");
-        assert_eq!(account.funds_held.to_string(), "0");
+        assert_eq!(account.funds_available.to_string(), "175");
+        assert!(account.funds_held.is_zero());
         assert!(account.locked);
     }
 
@@ -315,7 +745,7 @@ mod tests {
     fn test_cannot_dispute_same_transaction_twice() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("100.0"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
         account.dispute(1).expect("First dispute should succeed");
 
         // Second dispute should fail because transaction is no longer disputable
@@ -327,16 +757,16 @@ mod tests {
     fn test_multiple_disputes_on_different_transactions() {
         let mut account = Account::new(1);
 
-        account.deposit(1, create_amount("100.0"));
-        account.deposit(2, create_amount("50.0"));
-        account.deposit(3, create_amount("75.0"));
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.deposit(2, create_amount("50.0")).expect("Deposit should succeed");
+        account.deposit(3, create_amount("75.0")).expect("Deposit should succeed");
 
         // Dispute all three
         account.dispute(1).expect("Dispute 1 should succeed");
         account.dispute(2).expect("Dispute 2 should succeed");
         account.dispute(3).expect("Dispute 3 should succeed");
 
-        assert_eq!(account.funds_available.to_string(), "0");
+        assert!(account.funds_available.is_zero());
         assert_eq!(account.funds_held.to_string(), "225");
 
         // Resolve one
@@ -350,4 +780,396 @@ mod tests {
         assert_eq!(account.funds_held.to_string(), "75");
         assert!(account.locked);
     }
+
+    #[test]
+    fn test_transaction_count() {
+        let mut account = Account::new(1);
+        assert_eq!(account.transaction_count(), 0);
+
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.deposit(2, create_amount("50.0")).expect("Deposit should succeed");
+        account.withdraw(3, create_amount("25.0")).expect("Withdrawal should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+        account.resolve(1).expect("Resolve should succeed");
+        assert_eq!(account.transaction_count(), 5);
+
+        // Rejected transactions are not counted
+        assert!(account.dispute(999).is_err());
+        assert!(account.resolve(2).is_err());
+        assert!(account.chargeback(2).is_err());
+        assert_eq!(account.transaction_count(), 5);
+    }
+
+    #[test]
+    fn test_freeze_and_unfreeze() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+
+        account.freeze();
+        assert!(account.locked);
+        assert_eq!(account.funds_available.to_string(), "100");
+
+        account.unfreeze();
+        assert!(!account.locked);
+        assert_eq!(account.funds_available.to_string(), "100");
+        assert_eq!(account.transaction_count(), 3);
+    }
+
+    #[test]
+    fn test_held_matches_disputes() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.deposit(2, create_amount("50.0")).expect("Deposit should succeed");
+        account.withdraw(3, create_amount("30.0")).expect("Withdrawal should succeed");
+        assert!(account.held_matches_disputes());
+
+        account.dispute_with(1, DisputePolicy::Clamp).expect("Dispute should succeed");
+        account.dispute(3).expect("Dispute should succeed");
+        assert!(account.held_matches_disputes());
+        account.resolve(3).expect("Resolve should succeed");
+        assert!(account.held_matches_disputes());
+        account.dispute(2).expect("Dispute should succeed");
+        account.chargeback(1).expect("Chargeback should succeed");
+        assert!(account.held_matches_disputes());
+
+        account.funds_held = create_amount("1.0");
+        assert!(!account.held_matches_disputes());
+    }
+
+    #[test]
+    fn test_disputed_count() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.deposit(2, create_amount("50.0")).expect("Deposit should succeed");
+        account.deposit(3, create_amount("75.0")).expect("Deposit should succeed");
+
+        account.dispute(1).expect("Dispute 1 should succeed");
+        account.dispute(3).expect("Dispute 3 should succeed");
+        assert_eq!(account.disputed_count(), 2);
+
+        account.resolve(1).expect("Resolve should succeed");
+        assert_eq!(account.disputed_count(), 1);
+    }
+
+    #[test]
+    fn test_deposit_saturating_clamps_to_max() {
+        let mut account = Account::new(1);
+        account.deposit(1, Amount::MAX.checked_sub(create_amount("1.0")).unwrap()).expect("Deposit should succeed");
+
+        let applied = account.deposit_saturating(2, create_amount("5.0")).expect("Deposit should succeed");
+
+        assert_eq!(applied.to_string(), "1");
+        assert_eq!(account.funds_available, Amount::MAX);
+        account.dispute(2).expect("Dispute should succeed");
+        assert_eq!(account.funds_held.to_string(), "1");
+    }
+
+    #[test]
+    fn test_withdraw_saturating_clamps_to_min() {
+        let mut account = Account::new(1);
+        account.withdraw(1, Amount::MAX).expect("Withdrawal should succeed");
+
+        let applied = account.withdraw_saturating(2, Amount::MAX).expect("Withdrawal should succeed");
+
+        assert_eq!(applied.to_string(), "0.0001");
+        assert_eq!(account.funds_available, Amount::MIN);
+    }
+
+    #[test]
+    fn test_deposit_overflow_leaves_account_unchanged() {
+        let mut account = Account::new(1);
+        account.deposit(1, Amount::MAX).expect("Deposit should succeed");
+
+        let result = account.deposit(2, create_amount("1.0"));
+
+        assert!(matches!(result, Err(AccountError::Overflow(2))));
+        assert_eq!(account.funds_available, Amount::MAX);
+        assert_eq!(account.transaction_count(), 1);
+        assert!(account.dispute(2).is_err());
+    }
+
+    /// Wider backend with the same scale, for balances beyond the range of `Amount`.
+    type WideAmount = primitive_fixed_point_decimal::ConstScaleFpdec<i128, 4>;
+
+    impl Money for WideAmount {
+        const ZERO: Self = WideAmount::ZERO;
+        const MIN: Self = WideAmount::MIN;
+        const MAX: Self = WideAmount::MAX;
+
+        fn checked_add(self, other: Self) -> Option<Self> {
+            WideAmount::checked_add(self, other)
+        }
+
+        fn checked_sub(self, other: Self) -> Option<Self> {
+            WideAmount::checked_sub(self, other)
+        }
+    }
+
+    #[test]
+    fn test_wide_amount_backend_holds_large_balances() {
+        let large: WideAmount = "900000000000000.5".parse().expect("Failed to parse amount");
+        let mut account = Account::<WideAmount>::with_client(1);
+
+        account.deposit(1, large).expect("Deposit should succeed");
+        account.deposit(2, large).expect("Deposit should succeed");
+        account.deposit(3, large).expect("Deposit should succeed");
+        account.dispute(2).expect("Dispute should succeed");
+
+        assert_eq!(account.funds_available.to_string(), "1800000000000001");
+        assert_eq!(account.funds_held.to_string(), "900000000000000.5");
+        assert!(Amount::from_str(&account.funds_available.to_string()).is_err());
+        account.resolve(2).expect("Resolve should succeed");
+        assert_eq!(account.funds_available.to_string(), "2700000000000001.5");
+        assert!(account.funds_held.is_zero());
+    }
+
+    #[test]
+    fn test_close_clean_account() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("10.0")).expect("Deposit should succeed");
+
+        account.close().expect("Close should succeed");
+
+        assert_eq!(account.status(), AccountStatus::Closed);
+        assert!(account.is_terminal());
+        assert!(!account.locked);
+        assert_eq!(account.funds_available.to_string(), "10");
+    }
+
+    #[test]
+    fn test_adjust_undisputed_deposit() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.withdraw(2, create_amount("30.0")).expect("Withdrawal should succeed");
+
+        let delta = account.adjust(1, create_amount("90.0")).expect("Adjustment should succeed");
+
+        assert_eq!(delta.to_string(), "-10");
+        assert_eq!(account.funds_available.to_string(), "60");
+        account.dispute(1).expect("Dispute should succeed");
+        assert_eq!(account.funds_held.to_string(), "90");
+    }
+
+    #[test]
+    fn test_adjust_withdrawal() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.withdraw(2, create_amount("30.0")).expect("Withdrawal should succeed");
+
+        let delta = account.adjust(2, create_amount("20.0")).expect("Adjustment should succeed");
+
+        assert_eq!(delta.to_string(), "10");
+        assert_eq!(account.funds_available.to_string(), "80");
+        assert_eq!(account.direction(2), Some(Direction::Withdrawal));
+    }
+
+    #[test]
+    fn test_adjust_below_zero_rejected() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.withdraw(2, create_amount("30.0")).expect("Withdrawal should succeed");
+
+        let lowered_deposit = account.adjust(1, create_amount("20.0"));
+        let raised_withdrawal = account.adjust(2, create_amount("110.0"));
+
+        assert!(matches!(lowered_deposit, Err(AccountError::InsufficientFunds(1))));
+        assert!(matches!(raised_withdrawal, Err(AccountError::InsufficientFunds(2))));
+        assert_eq!(account.funds_available.to_string(), "70");
+        assert_eq!(account.adjust(1, create_amount("30.0")).expect("Adjustment should succeed").to_string(), "-70");
+        assert!(account.funds_available.is_zero());
+    }
+
+    #[test]
+    fn test_adjust_disputed_transaction_rejected() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+
+        let result = account.adjust(1, create_amount("90.0"));
+
+        assert!(matches!(result, Err(AccountError::AdjustDisputed(1))));
+        assert!(matches!(account.adjust(2, create_amount("1.0")), Err(AccountError::NoTransaction(2))));
+        assert!(account.funds_available.is_zero());
+        assert_eq!(account.funds_held.to_string(), "100");
+        account.resolve(1).expect("Resolve should succeed");
+        assert_eq!(account.funds_available.to_string(), "100");
+    }
+
+    #[test]
+    fn test_close_rejected_with_open_dispute() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("10.0")).expect("Deposit should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+
+        let result = account.close();
+
+        assert!(matches!(result, Err(AccountError::CannotCloseWithHeldFunds(1))));
+        assert_eq!(account.status(), AccountStatus::Active);
+    }
+
+    #[test]
+    fn test_reused_id_dispute_uses_latest_transaction() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.withdraw(1, create_amount("30.0")).expect("Withdrawal should succeed");
+
+        account.dispute(1).expect("Dispute should succeed");
+
+        // The withdrawal replaced the deposit, and its funds already left, so nothing is held
+        assert_eq!(account.direction(1), Some(Direction::Withdrawal));
+        assert_eq!(account.funds_available.to_string(), "70");
+        assert!(account.funds_held.is_zero());
+
+        let delta = account.chargeback(1).expect("Chargeback should succeed");
+
+        // Charging back the withdrawal pays its 30 back
+        assert_eq!(delta.to_string(), "30");
+        assert_eq!(account.funds_available.to_string(), "100");
+        assert!(account.funds_held.is_zero());
+    }
+
+    #[test]
+    fn test_resolve_withdrawal_dispute_keeps_balances() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.withdraw(2, create_amount("30.0")).expect("Withdrawal should succeed");
+        account.dispute(2).expect("Dispute should succeed");
+
+        account.resolve(2).expect("Resolve should succeed");
+
+        assert_eq!(account.funds_available.to_string(), "70");
+        assert!(account.funds_held.is_zero());
+        assert!(account.held_matches_disputes());
+    }
+
+    #[test]
+    fn test_resolve_keeps_direction() {
+        let mut account = Account::new(1);
+        account.withdraw(1, create_amount("30.0")).expect("Withdrawal should succeed");
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+
+        account.resolve(1).expect("Resolve should succeed");
+
+        assert_eq!(account.direction(1), Some(Direction::Deposit));
+        assert_eq!(account.funds_available.to_string(), "70");
+        assert!(account.funds_held.is_zero());
+        account.dispute(1).expect("Resolved transaction should be disputable again");
+        assert_eq!(account.funds_held.to_string(), "100");
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut account = Account::new(7);
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.deposit(2, create_amount("20.5")).expect("Deposit should succeed");
+        account.withdraw(3, create_amount("0.25")).expect("Withdrawal should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+
+        let snapshot = account.to_snapshot().expect("Failed to snapshot account");
+        let mut restored = Account::from_snapshot(&snapshot).expect("Failed to restore account");
+
+        assert_eq!(restored.client, 7);
+        assert_eq!(restored.funds_available.to_string(), "20.25");
+        assert_eq!(restored.funds_held.to_string(), "100");
+        assert_eq!(restored.transaction_count(), 4);
+        assert!(restored.is_disputed(1));
+        restored.resolve(1).expect("Resolve should succeed");
+        restored.dispute(2).expect("Dispute should succeed");
+        assert_eq!(restored.funds_available.to_string(), "99.75");
+        assert_eq!(restored.funds_held.to_string(), "20.5");
+        assert_eq!(restored.direction(3), Some(Direction::Withdrawal));
+    }
+
+    #[test]
+    fn test_hold_ratio() {
+        let mut account = Account::new(1);
+        assert_eq!(account.hold_ratio(), None);
+
+        account.deposit(1, create_amount("30.0")).expect("Deposit should succeed");
+        account.deposit(2, create_amount("10.0")).expect("Deposit should succeed");
+        assert_eq!(account.hold_ratio(), Some(0.0));
+
+        account.dispute(2).expect("Dispute should succeed");
+        assert_eq!(account.hold_ratio(), Some(0.25));
+
+        account.withdraw(3, create_amount("30.0")).expect("Withdrawal should succeed");
+        assert_eq!(account.hold_ratio(), Some(1.0));
+    }
+
+    #[test]
+    fn test_held_since_survives_snapshot_and_clears_on_resolve() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("10.0")).expect("Deposit should succeed");
+        account.dispute_at(1, DisputePolicy::AllowNegative, 42).expect("Dispute should succeed");
+
+        let snapshot = account.to_snapshot().expect("Failed to snapshot account");
+        let mut restored = Account::from_snapshot(&snapshot).expect("Failed to restore account");
+
+        assert_eq!(restored.held_since(1), Some(42));
+        restored.resolve(1).expect("Resolve should succeed");
+        assert_eq!(restored.held_since(1), None);
+    }
+
+    #[test]
+    fn test_snapshot_invalid() {
+        assert!(matches!(Account::from_snapshot("{"), Err(crate::error::Error::Json(_))));
+    }
+
+    fn withdrawn_deposit() -> Account {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("100.0")).expect("Deposit should succeed");
+        account.withdraw(2, create_amount("60.0")).expect("Withdrawal should succeed");
+        account
+    }
+
+    #[test]
+    fn test_dispute_policy_strict() {
+        let mut account = withdrawn_deposit();
+
+        let result = account.dispute_with(1, DisputePolicy::Strict);
+
+        assert!(matches!(result, Err(AccountError::InsufficientAvailableForDispute(1))));
+        assert_eq!(account.funds_available.to_string(), "40");
+        assert!(account.funds_held.is_zero());
+    }
+
+    #[test]
+    fn test_dispute_policy_allow_negative() {
+        let mut account = withdrawn_deposit();
+
+        account.dispute_with(1, DisputePolicy::AllowNegative).expect("Dispute should succeed");
+
+        assert_eq!(account.funds_available.to_string(), "-60");
+        assert_eq!(account.funds_held.to_string(), "100");
+    }
+
+    #[test]
+    fn test_dispute_policy_clamp() {
+        let mut account = withdrawn_deposit();
+
+        account.dispute_with(1, DisputePolicy::Clamp).expect("Dispute should succeed");
+
+        assert!(account.funds_available.is_zero());
+        assert_eq!(account.funds_held.to_string(), "40");
+        account.resolve(1).expect("Resolve should succeed");
+        assert_eq!(account.funds_available.to_string(), "40");
+        assert!(account.funds_held.is_zero());
+    }
+
+    #[test]
+    fn test_second_chargeback_on_locked_account() {
+        let mut account = Account::new(1);
+        account.deposit(1, create_amount("10.0")).expect("Deposit should succeed");
+        account.deposit(2, create_amount("5.0")).expect("Deposit should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+        account.chargeback(1).expect("Chargeback should succeed");
+        account.dispute(2).expect("Dispute should succeed");
+
+        let result = account.chargeback(2);
+
+        assert!(matches!(result, Err(AccountError::AccountLocked(2))));
+        assert!(account.is_disputed(2));
+        assert_eq!(account.funds_held.to_string(), "5");
+    }
 }
@@ -0,0 +1,84 @@
+use crate::account::Account;
+use crate::engine::Operation;
+use crate::{Amount, ClientId};
+use primitive_fixed_point_decimal::ConstScaleFpdec;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Sums are kept with an `i128` mantissa so the ledger can't overflow where balances don't.
+type Net = ConstScaleFpdec<i128, 4>;
+
+fn net(amount: Amount) -> Net {
+    Net::from_mantissa(i128::from(amount.mantissa()))
+}
+
+/// Net funds moved in and out of each client, tracked apart from the account balances.
+///
+/// Deposits and incoming transfers add to the net, withdrawals, outgoing transfers and fees
+/// subtract from it; adjustments and chargebacks change it by their effect on the account's
+/// funds. Disputes and resolves only move funds
+/// between available and held, so after any sequence of operations every account's
+/// `available + held` has to equal its net.
+#[derive(Debug, Default)]
+pub struct Audit {
+    net: HashMap<ClientId, Net>,
+}
+
+/// An account whose balances disagree with the funds moved in and out of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Discrepancy {
+    pub client: ClientId,
+    pub net: Net,
+    pub total: Net,
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "client {} holds {} available and held, but its deposits net of withdrawals, transfers, fees and chargebacks are {}",
+            self.client, self.total, self.net
+        )
+    }
+}
+
+impl Audit {
+    /// Books the funds a deposit, withdrawal or transfer applied for `client` moves.
+    pub(crate) fn record(&mut self, client: ClientId, operation: Operation) {
+        match operation {
+            Operation::Deposit { amount, .. } => self.credit(client, amount),
+            Operation::Withdrawal { amount, .. } => self.debit(client, amount),
+            Operation::Transfer { amount, to, .. } => {
+                self.debit(client, amount);
+                self.credit(to, amount);
+            }
+            _ => {}
+        }
+    }
+
+    /// Books funds entering the client, or leaving it for a negative amount such as an
+    /// adjustment lowering a deposit.
+    pub(crate) fn credit(&mut self, client: ClientId, amount: Amount) {
+        *self.net.entry(client).or_default() += net(amount);
+    }
+
+    /// Books funds leaving the client other than by an operation, such as a fee or chargeback.
+    pub(crate) fn debit(&mut self, client: ClientId, amount: Amount) {
+        *self.net.entry(client).or_default() -= net(amount);
+    }
+
+    /// Accounts whose `available + held` differs from their net, ordered by client id.
+    pub fn discrepancies(&self, accounts: &HashMap<ClientId, Account>) -> Vec<Discrepancy> {
+        let mut discrepancies: Vec<Discrepancy> = accounts
+            .values()
+            .map(|account| Discrepancy {
+                client: account.client,
+                net: self.net.get(&account.client).copied().unwrap_or_default(),
+                total: net(account.funds_available) + net(account.funds_held),
+            })
+            .filter(|discrepancy| discrepancy.net != discrepancy.total)
+            .collect();
+        discrepancies.sort_unstable_by_key(|discrepancy| discrepancy.client);
+        discrepancies
+    }
+}
@@ -0,0 +1,414 @@
+use kraken::engine::{LimitAction, SeedMode};
+use kraken::writer::{OutputOptions, SortBy};
+use kraken::{Amount, ClientId};
+use log::LevelFilter;
+
+pub const USAGE: &str = "[--merge] [--dialect <path>] [--summary] [--timing] [--output-digest] [--fail-on-warn] [--color] [--quiet | --verbose...] [--warn-empty] [--partial-on-error] [--explain <client>] [--seed-accounts <path> [--seed-mode overwrite|sum]] [--journal <path>] [--resume <journal>] [--since-tx <id>] [--max-tx-per-client <n> [--max-tx-action reject|warn]] [--snapshot-every <n>] [--dedupe-store <path>] [--dispute-detail <path>] [--apply-fee <amount>] [--max-hold-ratio <ratio>] [--check-invariants] [--with-checksum] [--with-transaction-count] [--with-disputed-count] [--deny-negative-total] [--with-totals] [--minor-units] [--table] [--sort-by balance|client] (<csv file or directory>... | --listen <addr>)";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub inputs: Vec<String>,
+    /// Read the transactions from a single TCP connection accepted on this address and answer on it
+    pub listen: Option<String>,
+    /// Merge the inputs in ascending transaction id order instead of processing them one after another
+    pub merge: bool,
+    /// Read the input format from this TOML file, overriding the matching settings
+    pub dialect: Option<String>,
+    /// Print run statistics to stderr
+    pub summary: bool,
+    /// Print the time spent parsing and serializing to stderr
+    pub timing: bool,
+    /// Print a SHA-256 digest of the complete output to stderr
+    pub output_digest: bool,
+    /// Exit with an error after processing if any warning was logged
+    pub fail_on_warn: bool,
+    /// Color log levels and error severities on stderr when it is a terminal
+    pub color: bool,
+    /// Only log errors and print nothing else to stderr
+    pub quiet: bool,
+    /// Log levels above the default warnings to show, raised by every `--verbose`
+    pub verbose: u8,
+    /// Warn when the inputs hold no transactions, not even a header for an empty file
+    pub warn_empty: bool,
+    /// On the first error, still write the accounts computed from the records before it
+    pub partial_on_error: bool,
+    /// Print every operation applied to this client with the resulting balances to stderr
+    pub explain: Option<ClientId>,
+    /// Preload opening balances from this `client,available,held,locked` CSV
+    pub seed_accounts: Option<String>,
+    /// Whether seeded accounts replace or add to accounts of the same client
+    pub seed_mode: SeedMode,
+    /// Append every applied transaction to this journal
+    pub journal: Option<String>,
+    /// Replay this journal before processing the inputs
+    pub resume: Option<String>,
+    /// Skip deposits, withdrawals and transfers with a transaction id up to this one
+    pub since_tx: Option<u64>,
+    /// Most transactions applied to a single client
+    pub max_tx_per_client: Option<u64>,
+    /// Whether transactions beyond `max_tx_per_client` are rejected or only warned about
+    pub max_tx_action: LimitAction,
+    /// Write the accounts to a numbered snapshot file in the working directory every this many records
+    pub snapshot_every: Option<u64>,
+    /// Skip deposits, withdrawals and transfers whose id is in this file and add the applied ones
+    pub dedupe_store: Option<String>,
+    /// Write the open disputes of every account to this file
+    pub dispute_detail: Option<String>,
+    /// Deduct this fee from every active account after processing
+    pub fee: Option<Amount>,
+    /// Warn about accounts holding more than this share of their total after processing
+    pub max_hold_ratio: Option<f64>,
+    /// Fail if any account's balances disagree with the funds moved in and out of it
+    pub check_invariants: bool,
+    pub output: OutputOptions,
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--listen" => options.listen = Some(value(&mut args, &arg)?),
+                "--merge" => options.merge = true,
+                "--dialect" => options.dialect = Some(value(&mut args, &arg)?),
+                "--summary" => options.summary = true,
+                "--timing" => options.timing = true,
+                "--output-digest" => options.output_digest = true,
+                "--fail-on-warn" => options.fail_on_warn = true,
+                "--color" => options.color = true,
+                "--quiet" => options.quiet = true,
+                "--verbose" => options.verbose = options.verbose.saturating_add(1),
+                "--warn-empty" => options.warn_empty = true,
+                "--partial-on-error" => options.partial_on_error = true,
+                "--explain" => {
+                    let client = value(&mut args, &arg)?;
+                    let client = client.parse().map_err(|_| format!("Invalid client {client} for --explain"))?;
+                    options.explain = Some(client);
+                }
+                "--seed-accounts" => options.seed_accounts = Some(value(&mut args, &arg)?),
+                "--seed-mode" => {
+                    options.seed_mode = match value(&mut args, &arg)?.as_str() {
+                        "overwrite" => SeedMode::Overwrite,
+                        "sum" => SeedMode::Sum,
+                        mode => return Err(format!("Invalid mode {mode} for --seed-mode, expected overwrite or sum")),
+                    };
+                }
+                "--journal" => options.journal = Some(value(&mut args, &arg)?),
+                "--resume" => options.resume = Some(value(&mut args, &arg)?),
+                "--since-tx" => {
+                    let tx = value(&mut args, &arg)?;
+                    let tx = tx.parse().map_err(|_| format!("Invalid transaction id {tx} for --since-tx"))?;
+                    options.since_tx = Some(tx);
+                }
+                "--max-tx-per-client" => {
+                    let max = value(&mut args, &arg)?;
+                    let max = max.parse().map_err(|_| format!("Invalid limit {max} for --max-tx-per-client"))?;
+                    options.max_tx_per_client = Some(max);
+                }
+                "--max-tx-action" => {
+                    options.max_tx_action = match value(&mut args, &arg)?.as_str() {
+                        "reject" => LimitAction::Reject,
+                        "warn" => LimitAction::Warn,
+                        action => return Err(format!("Invalid action {action} for --max-tx-action, expected reject or warn")),
+                    };
+                }
+                "--snapshot-every" => {
+                    let every = value(&mut args, &arg)?;
+                    let every = every
+                        .parse()
+                        .ok()
+                        .filter(|&every| every > 0)
+                        .ok_or_else(|| format!("Invalid record count {every} for --snapshot-every"))?;
+                    options.snapshot_every = Some(every);
+                }
+                "--dedupe-store" => options.dedupe_store = Some(value(&mut args, &arg)?),
+                "--apply-fee" => {
+                    let fee = value(&mut args, &arg)?;
+                    let fee = fee
+                        .parse::<Amount>()
+                        .ok()
+                        .filter(|fee| !fee.is_neg())
+                        .ok_or_else(|| format!("Invalid amount {fee} for --apply-fee"))?;
+                    options.fee = Some(fee);
+                }
+                "--max-hold-ratio" => {
+                    let ratio = value(&mut args, &arg)?;
+                    let ratio = ratio
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|ratio| (0.0..=1.0).contains(ratio))
+                        .ok_or_else(|| format!("Invalid ratio {ratio} for --max-hold-ratio, expected 0 to 1"))?;
+                    options.max_hold_ratio = Some(ratio);
+                }
+                "--check-invariants" => options.check_invariants = true,
+                "--dispute-detail" => options.dispute_detail = Some(value(&mut args, &arg)?),
+                "--with-checksum" => options.output.with_checksum = true,
+                "--with-transaction-count" => options.output.with_transaction_count = true,
+                "--with-disputed-count" => options.output.with_disputed_count = true,
+                "--deny-negative-total" => options.output.deny_negative_total = true,
+                "--with-totals" => options.output.with_totals = true,
+                "--minor-units" => options.output.minor_units = true,
+                "--table" => options.output.table = true,
+                "--sort-by" => {
+                    options.output.sort_by = match value(&mut args, &arg)?.as_str() {
+                        "client" => SortBy::Client,
+                        "balance" => SortBy::Balance,
+                        order => return Err(format!("Invalid order {order} for --sort-by, expected balance or client")),
+                    };
+                }
+                flag if flag.starts_with("--") => return Err(format!("Unknown option {flag}")),
+                _ => options.inputs.push(arg),
+            }
+        }
+        if options.quiet && options.verbose > 0 {
+            return Err("--quiet can't be combined with --verbose".to_string());
+        }
+        if options.listen.is_some() {
+            if !options.inputs.is_empty() {
+                return Err("--listen can't be combined with csv files".to_string());
+            }
+        } else if options.inputs.is_empty() {
+            return Err("Missing csv file".to_string());
+        }
+        Ok(options)
+    }
+
+    /// Log level chosen with `--quiet` or `--verbose`, overriding `RUST_LOG`; `None` leaves
+    /// it to `RUST_LOG`, warnings by default.
+    pub fn log_level(&self) -> Option<LevelFilter> {
+        match self.verbose {
+            _ if self.quiet => Some(LevelFilter::Error),
+            0 => None,
+            1 => Some(LevelFilter::Info),
+            2 => Some(LevelFilter::Debug),
+            _ => Some(LevelFilter::Trace),
+        }
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("Missing value for {flag}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_single_file() {
+        let options = Options::parse(args(&["transactions.csv"])).expect("Failed to parse options");
+
+        assert_eq!(options.inputs, vec!["transactions.csv"]);
+        assert!(!options.merge);
+    }
+
+    #[test]
+    fn test_parse_merge() {
+        let options = Options::parse(args(&["--merge", "a.csv", "b.csv", "--summary"]))
+            .expect("Failed to parse options");
+
+        assert_eq!(options.inputs, vec!["a.csv", "b.csv"]);
+        assert!(options.merge);
+        assert!(options.summary);
+    }
+
+    #[test]
+    fn test_parse_output_flags() {
+        let options = Options::parse(args(&["--with-checksum", "--with-transaction-count", "a.csv"]))
+            .expect("Failed to parse options");
+
+        assert!(options.output.with_checksum);
+        assert!(options.output.with_transaction_count);
+    }
+
+    #[test]
+    fn test_parse_sort_by() {
+        let options = Options::parse(args(&["--sort-by", "balance", "a.csv"])).expect("Failed to parse options");
+
+        assert_eq!(options.output.sort_by, SortBy::Balance);
+        assert_eq!(Options::parse(args(&["a.csv"])).expect("Failed to parse options").output.sort_by, SortBy::Client);
+        assert!(Options::parse(args(&["--sort-by", "total", "a.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_journal() {
+        let options = Options::parse(args(&["--resume", "old.journal", "--journal", "new.journal", "a.csv"]))
+            .expect("Failed to parse options");
+
+        assert_eq!(options.resume.as_deref(), Some("old.journal"));
+        assert_eq!(options.journal.as_deref(), Some("new.journal"));
+        assert_eq!(options.inputs, vec!["a.csv"]);
+    }
+
+    #[test]
+    fn test_parse_listen() {
+        let options = Options::parse(args(&["--listen", "127.0.0.1:7878"])).expect("Failed to parse options");
+
+        assert_eq!(options.listen.as_deref(), Some("127.0.0.1:7878"));
+        assert!(options.inputs.is_empty());
+        assert!(Options::parse(args(&["--listen", "127.0.0.1:7878", "a.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Options::parse(args(&[])).is_err());
+        assert!(Options::parse(args(&["a.csv", "--journal"])).is_err());
+        assert!(Options::parse(args(&["--unknown", "a.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_timing() {
+        let options = Options::parse(args(&["--timing", "a.csv"])).expect("Failed to parse options");
+
+        assert!(options.timing);
+        assert!(!options.summary);
+    }
+
+    #[test]
+    fn test_parse_warn_empty() {
+        let options = Options::parse(args(&["--warn-empty", "a.csv"])).expect("Failed to parse options");
+
+        assert!(options.warn_empty);
+        assert!(!options.fail_on_warn);
+    }
+
+    #[test]
+    fn test_parse_dialect() {
+        let options = Options::parse(args(&["--dialect", "partner.toml", "a.csv"])).expect("Failed to parse options");
+
+        assert_eq!(options.dialect.as_deref(), Some("partner.toml"));
+        assert!(Options::parse(args(&["a.csv", "--dialect"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_color() {
+        let options = Options::parse(args(&["--color", "a.csv"])).expect("Failed to parse options");
+
+        assert!(options.color);
+        assert!(!Options::parse(args(&["a.csv"])).expect("Failed to parse options").color);
+    }
+
+    #[test]
+    fn test_parse_quiet_and_verbose() {
+        let quiet = Options::parse(args(&["--quiet", "a.csv"])).expect("Failed to parse options");
+        let verbose = Options::parse(args(&["--verbose", "--verbose", "a.csv"])).expect("Failed to parse options");
+
+        assert_eq!(quiet.log_level(), Some(LevelFilter::Error));
+        assert_eq!(verbose.log_level(), Some(LevelFilter::Debug));
+        assert_eq!(Options::parse(args(&["a.csv"])).expect("Failed to parse options").log_level(), None);
+        assert!(Options::parse(args(&["--quiet", "--verbose", "a.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_output_digest() {
+        let options = Options::parse(args(&["--output-digest", "a.csv"])).expect("Failed to parse options");
+
+        assert!(options.output_digest);
+        assert!(!options.timing);
+    }
+
+    #[test]
+    fn test_parse_partial_on_error() {
+        let options = Options::parse(args(&["--partial-on-error", "a.csv"])).expect("Failed to parse options");
+
+        assert!(options.partial_on_error);
+        assert!(!Options::parse(args(&["a.csv"])).expect("Failed to parse options").partial_on_error);
+    }
+
+    #[test]
+    fn test_parse_explain() {
+        let options = Options::parse(args(&["--explain", "7", "a.csv"])).expect("Failed to parse options");
+        let invalid = Options::parse(args(&["--explain", "client", "a.csv"]));
+
+        assert_eq!(options.explain, Some(7));
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_parse_max_tx_per_client() {
+        let options = Options::parse(args(&["--max-tx-per-client", "100", "a.csv"])).expect("Failed to parse options");
+        let warning = Options::parse(args(&["--max-tx-per-client", "100", "--max-tx-action", "warn", "a.csv"]))
+            .expect("Failed to parse options");
+
+        assert_eq!(options.max_tx_per_client, Some(100));
+        assert_eq!(options.max_tx_action, LimitAction::Reject);
+        assert_eq!(warning.max_tx_action, LimitAction::Warn);
+        assert!(Options::parse(args(&["--max-tx-per-client", "-1", "a.csv"])).is_err());
+        assert!(Options::parse(args(&["--max-tx-action", "ignore", "a.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_seed_accounts() {
+        let options = Options::parse(args(&["--seed-accounts", "seed.csv", "--seed-mode", "sum", "a.csv"]))
+            .expect("Failed to parse options");
+
+        assert_eq!(options.seed_accounts.as_deref(), Some("seed.csv"));
+        assert_eq!(options.seed_mode, SeedMode::Sum);
+        assert_eq!(Options::parse(args(&["a.csv"])).expect("Failed to parse options").seed_mode, SeedMode::Overwrite);
+        assert!(Options::parse(args(&["--seed-mode", "merge", "a.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_snapshot_every() {
+        let options = Options::parse(args(&["--snapshot-every", "5", "a.csv"])).expect("Failed to parse options");
+
+        assert_eq!(options.snapshot_every, Some(5));
+        assert!(Options::parse(args(&["--snapshot-every", "0", "a.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_since_tx() {
+        let options = Options::parse(args(&["--since-tx", "42", "a.csv"])).expect("Failed to parse options");
+        let invalid = Options::parse(args(&["--since-tx", "-1", "a.csv"]));
+
+        assert_eq!(options.since_tx, Some(42));
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_parse_dedupe_store() {
+        let options = Options::parse(args(&["--dedupe-store", "seen.txt", "a.csv"])).expect("Failed to parse options");
+
+        assert_eq!(options.dedupe_store.as_deref(), Some("seen.txt"));
+    }
+
+    #[test]
+    fn test_parse_max_hold_ratio() {
+        let options = Options::parse(args(&["--max-hold-ratio", "0.8", "a.csv"])).expect("Failed to parse options");
+
+        assert_eq!(options.max_hold_ratio, Some(0.8));
+        assert!(Options::parse(args(&["--max-hold-ratio", "1.5", "a.csv"])).is_err());
+        assert!(Options::parse(args(&["--max-hold-ratio", "-0.1", "a.csv"])).is_err());
+        assert!(Options::parse(args(&["--max-hold-ratio", "NaN", "a.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_check_invariants() {
+        let options = Options::parse(args(&["--check-invariants", "a.csv"])).expect("Failed to parse options");
+
+        assert!(options.check_invariants);
+    }
+
+    #[test]
+    fn test_parse_apply_fee() {
+        let options = Options::parse(args(&["--apply-fee", "1.5", "a.csv"])).expect("Failed to parse options");
+
+        assert_eq!(options.fee.map(|fee| fee.to_string()).as_deref(), Some("1.5"));
+        assert!(Options::parse(args(&["--apply-fee", "-1", "a.csv"])).is_err());
+        assert!(Options::parse(args(&["--apply-fee", "fee", "a.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_dispute_detail() {
+        let options = Options::parse(args(&["--dispute-detail", "disputes.csv", "a.csv"]))
+            .expect("Failed to parse options");
+
+        assert_eq!(options.dispute_detail.as_deref(), Some("disputes.csv"));
+        assert_eq!(options.inputs, vec!["a.csv"]);
+    }
+}
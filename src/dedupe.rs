@@ -0,0 +1,42 @@
+use crate::prelude::*;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+
+/// Persistent set of applied deposit, withdrawal and transfer ids.
+///
+/// The file holds one transaction id per line. Ids are appended and flushed as they are
+/// recorded, so a re-run of the same input after a crash skips everything already applied.
+pub struct DedupeStore {
+    file: File,
+    seen: HashSet<u64>,
+}
+
+impl DedupeStore {
+    /// Opens the store at `path`, creating it if missing and loading the ids it holds.
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        let mut seen = HashSet::new();
+        for line in BufReader::new(&file).lines() {
+            let line = line?;
+            let line = line.trim();
+            if !line.is_empty() {
+                seen.insert(lexical_core::parse::<u64>(line.as_bytes())?);
+            }
+        }
+        Ok(DedupeStore { file, seen })
+    }
+
+    pub fn contains(&self, transaction_id: u64) -> bool {
+        self.seen.contains(&transaction_id)
+    }
+
+    /// Adds an applied transaction id, writing it only if it wasn't stored yet.
+    pub fn record(&mut self, transaction_id: u64) -> Result<()> {
+        if self.seen.insert(transaction_id) {
+            writeln!(self.file, "{transaction_id}")?;
+            self.file.flush()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+use crate::prelude::*;
+use crate::settings::Settings;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+/// Input format of a partner feed, read from a TOML file so a new feed needs one file
+/// instead of several settings. Every field left out keeps the configured setting.
+///
+/// ```toml
+/// delimiter = ";"
+/// has_headers = false
+/// columns = ["tx", "client", "type", "amount"]
+/// decimal_separator = ","
+///
+/// [type_aliases]
+/// credit = "deposit"
+/// debit = "withdrawal"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dialect {
+    pub delimiter: Option<char>,
+    pub has_headers: Option<bool>,
+    /// Order of the input columns, see `Settings.input_columns`
+    pub columns: Option<Vec<String>>,
+    pub decimal_separator: Option<char>,
+    /// Feed specific transaction type names mapped to the standard ones
+    pub type_aliases: Option<HashMap<String, String>>,
+}
+
+impl Dialect {
+    pub fn load(path: &str) -> Result<Self> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Overrides the settings named in the dialect.
+    pub fn apply(self, settings: &mut Settings) {
+        if let Some(delimiter) = self.delimiter {
+            settings.delimiter = delimiter;
+        }
+        if let Some(has_headers) = self.has_headers {
+            settings.has_headers = has_headers;
+        }
+        if let Some(columns) = self.columns {
+            settings.input_columns = Some(columns);
+        }
+        if let Some(decimal_separator) = self.decimal_separator {
+            settings.decimal_separator = decimal_separator;
+        }
+        if let Some(type_aliases) = self.type_aliases {
+            settings.type_aliases = type_aliases;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::reader::parse_bytes;
+    use crate::writer::write_accounts;
+
+    fn write_dialect(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("kraken-{}-{name}", std::process::id()));
+        fs::write(&path, contents).expect("Failed to write dialect");
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_dialect_parses_partner_feed() {
+        let path = write_dialect(
+            "partner.toml",
+            "delimiter = \";\"\nhas_headers = false\ncolumns = [\"tx\", \"client\", \"type\", \"amount\"]\ndecimal_separator = \",\"\n\n[type_aliases]\ncredit = \"deposit\"\ndebit = \"withdrawal\"\n",
+        );
+        let mut settings = Settings::default();
+
+        Dialect::load(&path).expect("Failed to load dialect").apply(&mut settings);
+        let accounts = parse_bytes(b"1;1;credit;100,5\n2;2;deposit;3\n3;1;debit;0,25\n", &settings)
+            .expect("Failed to parse partner feed");
+
+        assert_eq!(
+            write_accounts(accounts).expect("Failed to write accounts"),
+            "client,available,held,total,locked,status\n1,100.25,0,100.25,false,active\n2,3,0,3,false,active\n"
+        );
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_dialect_keeps_unnamed_settings() {
+        let path = write_dialect("partial.toml", "delimiter = \"|\"\n");
+        let mut settings = Settings {
+            decimal_separator: ',',
+            ..Settings::default()
+        };
+
+        Dialect::load(&path).expect("Failed to load dialect").apply(&mut settings);
+
+        assert_eq!(settings.delimiter, '|');
+        assert_eq!(settings.decimal_separator, ',');
+        assert!(settings.has_headers);
+        assert!(settings.input_columns.is_none());
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_dialect_rejects_unknown_keys() {
+        let path = write_dialect("typo.toml", "delimeter = \";\"\n");
+
+        assert!(matches!(Dialect::load(&path), Err(Error::Toml(_))));
+        fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,2117 @@
+use crate::account::{Account, AccountError};
+use crate::audit::{Audit, Discrepancy};
+use crate::dedupe::DedupeStore;
+use crate::error::Error;
+use crate::journal::Journal;
+use crate::prelude::*;
+use crate::reader::{FixedWidthReader, MergeByTransactionId, Transaction, TransactionReader, TransactionType};
+use crate::settings::{OverflowMode, Settings};
+use crate::telemetry;
+use crate::writer::{write_borrowed_accounts_to, write_borrowed_currency_accounts_to, OutputOptions};
+use crate::{Amount, ClientId};
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::mem;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Number of records processed between two checks of the processing deadline.
+const DEADLINE_CHECK_INTERVAL: u64 = 4096;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    /// Records read, including one that failed processing
+    pub records: u64,
+    /// Disputes of a transaction id the client has no deposit or withdrawal for
+    pub orphan_disputes: u64,
+    /// Deposits, withdrawals and chargebacks ignored because the account is locked or closed
+    pub skipped: u64,
+    /// Invalid rows skipped because `Settings.skip_invalid_rows` is set
+    pub errors: u64,
+    /// Rows already applied by an earlier run, per the `set_since_tx` offset or the dedupe store
+    pub replayed: u64,
+    /// Time spent reading and applying records, excluding opening inputs and writing output
+    pub processing: Duration,
+}
+
+impl Stats {
+    /// Records processed per second of processing time, zero before anything was processed.
+    pub fn records_per_second(&self) -> f64 {
+        let seconds = self.processing.as_secs_f64();
+        if seconds > 0.0 {
+            self.records as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "records: {}, skipped: {}, errors: {}, replayed: {}, orphan disputes: {}, records/s: {:.0}",
+            self.records,
+            self.skipped,
+            self.errors,
+            self.replayed,
+            self.orphan_disputes,
+            self.records_per_second()
+        )
+    }
+}
+
+/// Wall-clock time spent in the two phases of a run.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Timing {
+    /// Reading and applying the transactions
+    pub parsing: Duration,
+    /// Writing the account output
+    pub serialization: Duration,
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "parsing: {:.3} ms, serialization: {:.3} ms",
+            self.parsing.as_secs_f64() * 1000.0,
+            self.serialization.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// A single account operation, independent of the input it was read from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Deposit { tx: u64, amount: Amount },
+    Withdrawal { tx: u64, amount: Amount },
+    Dispute { tx: u64 },
+    Resolve { tx: u64 },
+    Chargeback { tx: u64 },
+    Freeze { tx: u64 },
+    Unfreeze { tx: u64 },
+    Close { tx: u64 },
+    /// Moves `amount` from the available funds of the operation's client to client `to`
+    Transfer { tx: u64, amount: Amount, to: ClientId },
+    /// Corrects the amount of the earlier deposit or withdrawal `tx` to `amount`
+    Adjust { tx: u64, amount: Amount },
+}
+
+impl Operation {
+    pub fn tx(self) -> u64 {
+        match self {
+            Operation::Deposit { tx, .. }
+            | Operation::Withdrawal { tx, .. }
+            | Operation::Dispute { tx }
+            | Operation::Resolve { tx }
+            | Operation::Chargeback { tx }
+            | Operation::Freeze { tx }
+            | Operation::Unfreeze { tx }
+            | Operation::Close { tx }
+            | Operation::Transfer { tx, .. }
+            | Operation::Adjust { tx, .. } => tx,
+        }
+    }
+
+    /// The amount moved by a deposit, withdrawal or transfer, or set by an adjustment.
+    pub fn amount(self) -> Option<Amount> {
+        match self {
+            Operation::Deposit { amount, .. }
+            | Operation::Withdrawal { amount, .. }
+            | Operation::Transfer { amount, .. }
+            | Operation::Adjust { amount, .. } => Some(amount),
+            _ => None,
+        }
+    }
+
+    pub fn kind(self) -> TransactionType {
+        match self {
+            Operation::Deposit { .. } => TransactionType::Deposit,
+            Operation::Withdrawal { .. } => TransactionType::Withdrawal,
+            Operation::Dispute { .. } => TransactionType::Dispute,
+            Operation::Resolve { .. } => TransactionType::Resolve,
+            Operation::Chargeback { .. } => TransactionType::Chargeback,
+            Operation::Freeze { .. } => TransactionType::Freeze,
+            Operation::Unfreeze { .. } => TransactionType::Unfreeze,
+            Operation::Close { .. } => TransactionType::Close,
+            Operation::Transfer { .. } => TransactionType::Transfer,
+            Operation::Adjust { .. } => TransactionType::Adjust,
+        }
+    }
+
+    /// The receiving client of a transfer.
+    pub fn destination(self) -> Option<ClientId> {
+        match self {
+            Operation::Transfer { to, .. } => Some(to),
+            _ => None,
+        }
+    }
+}
+
+/// Where and how often intermediate account snapshots are written.
+struct Snapshots {
+    every: u64,
+    dir: PathBuf,
+    options: OutputOptions,
+}
+
+/// What happens to a transaction of a client that already reached its transaction limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitAction {
+    /// Fail with `Error::TransactionLimitExceeded`
+    #[default]
+    Reject,
+    /// Warn once per client and apply the transactions anyway
+    Warn,
+}
+
+/// How a seeded account is combined with an account of the same client that already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeedMode {
+    /// Replace the existing account, including its open disputes
+    #[default]
+    Overwrite,
+    /// Add the seeded balances to the existing ones; the account is locked if either is
+    Sum,
+}
+
+/// Applies transactions to a set of accounts and keeps statistics about the run.
+pub struct Engine<'a> {
+    settings: &'a Settings,
+    accounts: HashMap<ClientId, Account>,
+    stats: Stats,
+    started: Instant,
+    journal: Option<Journal>,
+    explain: Option<(ClientId, Vec<String>)>,
+    /// Highest deposit or withdrawal transaction id seen, for `Settings.warn_out_of_order`
+    max_movement_tx: Option<u64>,
+    /// Deposits, withdrawals and transfers up to this transaction id are skipped as already applied
+    since_tx: Option<u64>,
+    dedupe: Option<DedupeStore>,
+    audit: Option<Audit>,
+    stop: Option<Arc<AtomicBool>>,
+    snapshots: Option<Snapshots>,
+    max_tx_per_client: Option<(u64, LimitAction)>,
+    /// Accounts per currency when `Settings.multi_currency` is set, `accounts` then stays empty
+    currencies: BTreeMap<String, HashMap<ClientId, Account>>,
+    /// Currency whose accounts `in_currency` swapped into `accounts`, for the journal
+    currency: Option<String>,
+    /// Creates the account of a client seen for the first time, `Account::new` when absent
+    account_factory: Option<Box<dyn Fn(ClientId) -> Account + 'a>>,
+    /// Line of the row that stopped processing, when it is known
+    failed_line: Option<u64>,
+}
+
+impl<'a> Engine<'a> {
+    pub fn new(settings: &'a Settings) -> Self {
+        Engine {
+            settings,
+            accounts: HashMap::new(),
+            stats: Stats::default(),
+            started: Instant::now(),
+            journal: None,
+            explain: None,
+            max_movement_tx: None,
+            since_tx: None,
+            dedupe: None,
+            audit: None,
+            stop: None,
+            snapshots: None,
+            max_tx_per_client: None,
+            currencies: BTreeMap::new(),
+            currency: None,
+            account_factory: None,
+            failed_line: None,
+        }
+    }
+
+    /// Creates the accounts of new clients with `factory` instead of `Account::new`, e.g. to
+    /// start them with a balance. The factory should return an account of the client it's given.
+    pub fn set_account_factory(&mut self, factory: impl Fn(ClientId) -> Account + 'a) {
+        self.account_factory = Some(Box::new(factory));
+    }
+
+    /// Records a human-readable line for every operation applied to `client` from now on.
+    pub fn set_explain(&mut self, client: ClientId) {
+        self.explain = Some((client, Vec::new()));
+    }
+
+    /// The lines recorded since `set_explain`, empty when no client is explained.
+    pub fn explanation(&self) -> &[String] {
+        self.explain.as_ref().map_or(&[], |(_, lines)| lines.as_slice())
+    }
+
+    /// Skips deposits, withdrawals and transfers with a transaction id up to `since_tx`, which an
+    /// earlier run already applied. Disputes, resolves and chargebacks are still applied; one
+    /// referencing such an id that finds no matching transaction or dispute is skipped as well,
+    /// since the earlier run either handled it or its state was not restored.
+    pub fn set_since_tx(&mut self, since_tx: u64) {
+        self.since_tx = Some(since_tx);
+    }
+
+    /// Skips deposits, withdrawals and transfers whose id is in the store, and records the id of
+    /// every one applied from now on. Disputes, resolves and chargebacks are always applied.
+    pub fn set_dedupe_store(&mut self, store: DedupeStore) {
+        self.dedupe = Some(store);
+    }
+
+    /// Tracks the funds moved in and out of every client from now on, to compare against the
+    /// balances with `discrepancies`.
+    pub fn set_audit(&mut self, audit: Audit) {
+        self.audit = Some(audit);
+    }
+
+    /// Preloads opening balances, e.g. from `reader::read_seed_accounts`. Seeded held funds
+    /// belong to no dispute, so they can't be released by a resolve, and are recorded as held
+    /// from the start for `held_drift`.
+    pub fn seed_accounts(&mut self, accounts: impl IntoIterator<Item = Account>, mode: SeedMode) -> Result<()> {
+        // Seed files carry no currency to file the balances under
+        if self.settings.multi_currency {
+            return Err(Error::UnsupportedWithMultiCurrency("Seeding accounts"));
+        }
+        for seed in accounts {
+            let client = seed.client;
+            let mut account = match (mode, self.accounts.get(&client)) {
+                (SeedMode::Sum, Some(existing)) => {
+                    let sum = |a: Amount, b: Amount| a.checked_add(b).ok_or(Error::TotalOverflow(client));
+                    let mut account = existing.clone();
+                    account.funds_available = sum(existing.funds_available, seed.funds_available)?;
+                    account.funds_held = sum(existing.funds_held, seed.funds_held)?;
+                    account.locked |= seed.locked;
+                    account
+                }
+                _ => seed,
+            };
+            // Everything held beyond the open disputes was seeded
+            let disputed = account
+                .open_disputes()
+                .try_fold(Amount::ZERO, |sum, (_, held)| sum.checked_add(held))
+                .ok_or(Error::TotalOverflow(client))?;
+            let opening_held = account.funds_held.checked_sub(disputed).ok_or(Error::TotalOverflow(client))?;
+            account.set_opening_held(opening_held);
+            if let Some(audit) = &mut self.audit {
+                if let Some(existing) = self.accounts.get(&client) {
+                    audit.debit(client, existing.funds_available);
+                    audit.debit(client, existing.funds_held);
+                }
+                audit.credit(client, account.funds_available);
+                audit.credit(client, account.funds_held);
+            }
+            self.accounts.insert(client, account);
+        }
+        Ok(())
+    }
+
+    /// Accounts whose balances disagree with the audit, empty when no audit is set.
+    pub fn discrepancies(&self) -> Vec<Discrepancy> {
+        self.audit.as_ref().map_or_else(Vec::new, |audit| audit.discrepancies(&self.accounts))
+    }
+
+    /// Clients whose held funds differ from the sum held by their open disputes, ordered by
+    /// client id.
+    pub fn held_drift(&self) -> Vec<ClientId> {
+        let mut clients: Vec<ClientId> = self
+            .accounts
+            .values()
+            .filter(|account| !account.held_matches_disputes())
+            .map(|account| account.client)
+            .collect();
+        clients.sort_unstable();
+        clients
+    }
+
+    /// Limits the transactions applied to a single client to `max`, counted like
+    /// `Account::transaction_count`, to catch feeds stuck in a loop.
+    pub fn set_max_tx_per_client(&mut self, max: u64, action: LimitAction) {
+        self.max_tx_per_client = Some((max, action));
+    }
+
+    /// Stops reading further records once `stop` is set, e.g. from a signal handler, failing
+    /// with `Error::Interrupted` while keeping the accounts computed so far.
+    pub fn set_stop_flag(&mut self, stop: Arc<AtomicBool>) {
+        self.stop = Some(stop);
+    }
+
+    /// Writes the accounts to `snapshot-<records>.csv` in `dir` after every `every` records,
+    /// formatted like the final output with `options`, for inspecting long runs.
+    pub fn set_snapshot_every(&mut self, every: u64, dir: impl Into<PathBuf>, options: OutputOptions) {
+        self.snapshots = Some(Snapshots { every: every.max(1), dir: dir.into(), options });
+    }
+
+    /// Records every transaction applied from now on in the journal.
+    pub fn set_journal(&mut self, journal: Journal) {
+        self.journal = Some(journal);
+    }
+
+    /// Rebuilds account state from a journal written by an earlier run.
+    pub fn replay_journal(&mut self, path: &str) -> Result<u64> {
+        info!("Replaying journal {path}");
+        // Journals are always written in the canonical format, whatever the input dialect
+        let journal_settings = Settings {
+            buffer: self.settings.buffer.clone(),
+            input_columns: Some(Journal::COLUMNS.iter().map(|column| column.to_string()).collect()),
+            allow_transfers: self.settings.allow_transfers,
+            multi_currency: self.settings.multi_currency,
+            ..Default::default()
+        };
+        let file = BufReader::with_capacity(journal_settings.buffer_capacity(), File::open(path)?);
+        self.process(TransactionReader::new(file, &journal_settings)?)
+    }
+
+    pub fn accounts(&self) -> &HashMap<ClientId, Account> {
+        &self.accounts
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Line of the row whose error stopped processing, including errors that don't carry one.
+    pub fn failed_line(&self) -> Option<u64> {
+        self.failed_line
+    }
+
+    pub fn into_accounts(self) -> HashMap<ClientId, Account> {
+        self.accounts
+    }
+
+    /// The accounts of every currency seen, for `Settings.multi_currency`.
+    pub fn currency_accounts(&self) -> &BTreeMap<String, HashMap<ClientId, Account>> {
+        &self.currencies
+    }
+
+    pub fn into_currency_accounts(self) -> BTreeMap<String, HashMap<ClientId, Account>> {
+        self.currencies
+    }
+
+    /// Deducts `fee` from the available funds of every account that is neither frozen nor
+    /// closed. Accounts that can't cover the fee are left unchanged with a warning. Returns the
+    /// number of accounts charged.
+    pub fn apply_fee(&mut self, fee: Amount) -> u64 {
+        let mut charged = 0;
+        for account in in_client_order(&mut self.accounts).filter(|account| !account.is_terminal()) {
+            if account.charge_fee(fee) {
+                charged += 1;
+                if let Some(audit) = &mut self.audit {
+                    audit.debit(account.client, fee);
+                }
+            } else {
+                warn!(
+                    "Not charging fee of {fee} to client {}: available funds {} don't cover it",
+                    account.client, account.funds_available
+                );
+            }
+        }
+        info!("Charged a fee of {fee} to {charged} accounts");
+        charged
+    }
+
+    /// Warns about every account holding more than `max_ratio` of its total, see
+    /// `Account::hold_ratio`. Returns the flagged clients in ascending order.
+    pub fn check_hold_ratio(&self, max_ratio: f64) -> Vec<ClientId> {
+        let mut flagged: Vec<(ClientId, f64)> = self
+            .accounts
+            .values()
+            .filter_map(|account| account.hold_ratio().map(|ratio| (account.client, ratio)))
+            .filter(|&(_, ratio)| ratio > max_ratio)
+            .collect();
+        flagged.sort_unstable_by_key(|&(client, _)| client);
+        for &(client, ratio) in &flagged {
+            warn!("Client {client} holds {ratio:.4} of its total, above the maximum hold ratio of {max_ratio}");
+        }
+        flagged.into_iter().map(|(client, _)| client).collect()
+    }
+
+    /// Processes the files one after another, as fixed-width records with `Settings.fixed_width`.
+    pub fn process_files(&mut self, paths: &[&str]) -> Result<()> {
+        for path in paths {
+            info!("Processing transactions from {path}");
+            let records_read = if self.settings.fixed_width.is_some() {
+                self.process(FixedWidthReader::from_path(path, self.settings)?)?
+            } else {
+                self.process(TransactionReader::from_path(path, self.settings)?)?
+            };
+            info!(
+                "Processed {records_read} records from {path} into {} accounts in {:?}",
+                self.accounts.len(),
+                self.started.elapsed()
+            );
+        }
+        Ok(())
+    }
+
+    /// Processes CSV transactions read from `input` until it ends, such as a network connection.
+    /// `source` names the input in log messages.
+    pub fn process_stream<R: Read>(&mut self, input: R, source: &str) -> Result<()> {
+        info!("Processing transactions from {source}");
+        let records_read = self.process(TransactionReader::new(input, self.settings)?)?;
+        info!(
+            "Processed {records_read} records from {source} into {} accounts in {:?}",
+            self.accounts.len(),
+            self.started.elapsed()
+        );
+        Ok(())
+    }
+
+    /// Processes the files as one stream merged in ascending transaction id order.
+    pub fn process_merged(&mut self, paths: &[&str]) -> Result<()> {
+        info!("Processing transactions merged from {}", paths.join(", "));
+        let readers = paths
+            .iter()
+            .map(|path| TransactionReader::from_path(path, self.settings))
+            .collect::<Result<Vec<_>>>()?;
+        let records_read = self.process(MergeByTransactionId::new(readers))?;
+        info!(
+            "Processed {records_read} merged records into {} accounts in {:?}",
+            self.accounts.len(),
+            self.started.elapsed()
+        );
+        Ok(())
+    }
+
+    /// Applies all transactions, returning the number of records read.
+    pub fn process<I>(&mut self, transactions: I) -> Result<u64>
+    where
+        I: IntoIterator<Item = Result<Transaction>>,
+    {
+        let started = Instant::now();
+        let result = self.process_records(transactions);
+        self.stats.processing += started.elapsed();
+        telemetry::accounts_locked(&self.accounts);
+        result
+    }
+
+    fn process_records<I>(&mut self, transactions: I) -> Result<u64>
+    where
+        I: IntoIterator<Item = Result<Transaction>>,
+    {
+        let deadline = self.settings.max_duration();
+        let mut records_read: u64 = 0;
+        let mut transactions = transactions.into_iter();
+        loop {
+            if self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed)) {
+                return Err(Error::Interrupted(self.stats.records));
+            }
+            let Some(transaction) = transactions.next() else {
+                break;
+            };
+            records_read += 1;
+            self.stats.records += 1;
+            if let Some(deadline) = deadline
+                && self.stats.records.is_multiple_of(DEADLINE_CHECK_INTERVAL)
+                && self.started.elapsed() > deadline
+            {
+                return Err(Error::Timeout(deadline.as_millis() as u64));
+            }
+
+            let transaction = transaction.inspect_err(|_| telemetry::parse_error());
+            let line = transaction.as_ref().map_or_else(Error::line, |transaction| Some(transaction.line));
+            if let Err(err) = transaction.and_then(|transaction| self.apply(&transaction)) {
+                self.skip_invalid_row(err).inspect_err(|_| self.failed_line = line)?;
+            }
+            if let Some(snapshots) = &self.snapshots
+                && self.stats.records.is_multiple_of(snapshots.every)
+            {
+                self.write_snapshot(snapshots)?;
+            }
+        }
+        Ok(records_read)
+    }
+
+    fn write_snapshot(&self, snapshots: &Snapshots) -> Result<()> {
+        let path = snapshots.dir.join(format!("snapshot-{}.csv", self.stats.records));
+        let out = BufWriter::new(File::create(path)?);
+        if self.settings.multi_currency {
+            write_borrowed_currency_accounts_to(&self.currencies, &snapshots.options, out)
+        } else {
+            write_borrowed_accounts_to(&self.accounts, &snapshots.options, out)
+        }
+    }
+
+    /// Gives the error back unless invalid rows are skipped and the error budget isn't exhausted.
+    fn skip_invalid_row(&mut self, err: Error) -> Result<()> {
+        if !self.settings.skip_invalid_rows || !err.is_row_error() {
+            return Err(err);
+        }
+        self.stats.errors += 1;
+        warn!("Skipping invalid row: {err}");
+        if let Some(max_errors) = self.settings.max_errors
+            && self.stats.errors > max_errors
+        {
+            return Err(Error::TooManyErrors(self.stats.errors));
+        }
+        Ok(())
+    }
+
+    /// Applies a single transaction to the account of its client.
+    pub fn apply(&mut self, transaction: &Transaction) -> Result<()> {
+        if transaction.kind.is_admin() && !self.settings.allow_admin_ops {
+            return Err(Error::AdminOperationNotAllowed(transaction.line));
+        }
+        if transaction.kind == TransactionType::Transfer && !self.settings.allow_transfers {
+            return Err(Error::TransferNotAllowed(transaction.line));
+        }
+        match transaction.kind {
+            TransactionType::Deposit | TransactionType::Withdrawal => {
+                self.max_movement_tx = self.max_movement_tx.max(Some(transaction.tx));
+            }
+            TransactionType::Dispute
+                if self.settings.warn_out_of_order && self.max_movement_tx.is_none_or(|max| transaction.tx > max) =>
+            {
+                warn!(
+                    "Dispute on line {} references transaction {} beyond the highest deposit or withdrawal seen, input may be out of order",
+                    transaction.line, transaction.tx
+                );
+            }
+            _ => {}
+        }
+        let already_applied = self.since_tx.is_some_and(|since_tx| transaction.tx <= since_tx);
+        let deduplicated = self.dedupe.as_ref().is_some_and(|store| store.contains(transaction.tx));
+        if (already_applied || deduplicated) && transaction.kind.carries_amount() {
+            self.stats.replayed += 1;
+            return Ok(());
+        }
+        let operation = self.operation(transaction)?;
+        let currency = transaction.currency.as_deref().filter(|_| self.settings.multi_currency);
+        let result = self.in_currency(currency, |engine| {
+            engine.apply_operation(transaction.client, operation, transaction.line)
+        });
+        match result {
+            Err(err @ (Error::NoTransaction(..) | Error::NoDispute(..))) if already_applied => {
+                warn!("Skipping {} of transaction {} already processed: {err}", transaction.kind.as_str(), transaction.tx);
+                self.stats.replayed += 1;
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Runs `apply` against the accounts of `currency`, or the plain accounts without one.
+    fn in_currency<T>(&mut self, currency: Option<&str>, apply: impl FnOnce(&mut Self) -> T) -> T {
+        let Some(currency) = currency else {
+            return apply(self);
+        };
+        let mut accounts = self.currencies.remove(currency).unwrap_or_default();
+        mem::swap(&mut self.accounts, &mut accounts);
+        self.currency = Some(currency.to_string());
+        let result = apply(self);
+        let currency = self.currency.take().unwrap_or_default();
+        mem::swap(&mut self.accounts, &mut accounts);
+        self.currencies.insert(currency, accounts);
+        result
+    }
+
+    fn operation(&self, transaction: &Transaction) -> Result<Operation> {
+        let tx = transaction.tx;
+        let amount = || {
+            let amount = transaction
+                .amount
+                .or(self.settings.empty_amount_as_zero.then_some(Amount::ZERO))
+                .ok_or(Error::MissingAmount(transaction.line))?;
+            if let Some(max_amount) = self.settings.max_transaction_amount
+                && amount > max_amount
+            {
+                return Err(Error::AmountTooLarge(transaction.line));
+            }
+            Ok(amount)
+        };
+        Ok(match transaction.kind {
+            TransactionType::Deposit => Operation::Deposit { tx, amount: amount()? },
+            TransactionType::Withdrawal => Operation::Withdrawal { tx, amount: amount()? },
+            TransactionType::Dispute => Operation::Dispute { tx },
+            TransactionType::Resolve => Operation::Resolve { tx },
+            TransactionType::Chargeback => Operation::Chargeback { tx },
+            TransactionType::Freeze => Operation::Freeze { tx },
+            TransactionType::Unfreeze => Operation::Unfreeze { tx },
+            TransactionType::Close => Operation::Close { tx },
+            TransactionType::Adjust => Operation::Adjust { tx, amount: amount()? },
+            TransactionType::Transfer => Operation::Transfer {
+                tx,
+                amount: amount()?,
+                to: transaction
+                    .dest_client
+                    .ok_or(Error::MissingDestinationClient(transaction.line))?,
+            },
+        })
+    }
+
+    /// Applies an operation to the account of `client`; `line_number` is only used in errors.
+    pub fn apply_operation(&mut self, client: ClientId, mut operation: Operation, line_number: u64) -> Result<()> {
+        let transaction_id = operation.tx();
+        if self.settings.require_prior_activity
+            && operation.kind().references_prior()
+            && !self.accounts.contains_key(&client)
+        {
+            return Err(Error::UnknownClient(client, line_number));
+        }
+        self.reserve_account(client, line_number)?;
+        let account = self
+            .accounts
+            .entry(client)
+            .or_insert_with_key(|&client| new_account(&self.account_factory, client));
+
+        if account.is_terminal() && (operation.amount().is_some() || operation.kind().references_prior()) {
+            if operation.amount().is_none() && self.settings.rejects_disputes_on_locked() {
+                return Err(Error::AccountLocked(transaction_id, line_number));
+            }
+            warn!(
+                "Ignoring {} of transaction {transaction_id} on line {line_number}: client {client} is {}",
+                operation.kind().as_str(),
+                account.status().as_str(),
+            );
+            self.stats.skipped += 1;
+            if let Some((explained, lines)) = &mut self.explain
+                && *explained == client
+            {
+                lines.push(format!("{} ignored, account is {}", describe(operation), account.status().as_str()));
+            }
+            return Ok(());
+        }
+        if let Some((max, action)) = self.max_tx_per_client
+            && account.transaction_count() >= max
+        {
+            match action {
+                LimitAction::Reject => return Err(Error::TransactionLimitExceeded(client, line_number, max)),
+                LimitAction::Warn if account.transaction_count() == max => {
+                    warn!("Client {client} exceeds the limit of {max} transactions on line {line_number}");
+                }
+                LimitAction::Warn => {}
+            }
+        }
+        if let Operation::Transfer { amount, to, .. } = operation {
+            return self.apply_transfer(client, to, operation, amount, line_number);
+        }
+
+        match operation {
+            Operation::Deposit { amount, .. } | Operation::Withdrawal { amount, .. } => {
+                let deposit = matches!(operation, Operation::Deposit { .. });
+                let applied = match self.settings.on_overflow {
+                    OverflowMode::Saturate if deposit => account.deposit_saturating(transaction_id, amount),
+                    OverflowMode::Saturate => account.withdraw_saturating(transaction_id, amount),
+                    _ if deposit => account.deposit(transaction_id, amount).map(|()| amount),
+                    _ => account.withdraw(transaction_id, amount).map(|()| amount),
+                };
+                match applied {
+                    Ok(applied) if applied != amount => {
+                        warn!(
+                            "Clamped the available funds of client {client} applying transaction {transaction_id} on line {line_number}"
+                        );
+                        // Journal and audit see what was actually applied
+                        operation = if deposit {
+                            Operation::Deposit { tx: transaction_id, amount: applied }
+                        } else {
+                            Operation::Withdrawal { tx: transaction_id, amount: applied }
+                        };
+                    }
+                    Ok(_) => {}
+                    Err(AccountError::Overflow(_)) if self.settings.on_overflow == OverflowMode::Skip => {
+                        warn!("Skipping transaction {transaction_id} on line {line_number}: balance of client {client} would overflow");
+                        self.stats.skipped += 1;
+                        return Ok(());
+                    }
+                    Err(err) => return Err(account_error(err, line_number)),
+                }
+            }
+            Operation::Dispute { .. } if self.settings.idempotent_dispute && account.is_disputed(transaction_id) => {
+                warn!("Ignoring dispute of transaction {transaction_id} on line {line_number}: it is already disputed");
+                self.stats.skipped += 1;
+                return Ok(());
+            }
+            Operation::Dispute { .. } => {
+                let result = account.dispute_at(transaction_id, self.settings.effective_dispute_policy(), self.stats.records);
+                if let Err(AccountError::NoTransaction(_)) = result
+                    && !account.is_disputed(transaction_id)
+                {
+                    self.stats.orphan_disputes += 1;
+                }
+                result.map_err(|err| account_error(err, line_number))?;
+            }
+            Operation::Resolve { .. } => {
+                account
+                    .resolve(transaction_id)
+                    .map_err(|err| settle_error(&self.accounts, err, client, line_number))?;
+            }
+            Operation::Chargeback { .. } => {
+                let delta = account
+                    .chargeback(transaction_id)
+                    .map_err(|err| settle_error(&self.accounts, err, client, line_number))?;
+                if let Some(audit) = &mut self.audit {
+                    audit.credit(client, delta);
+                }
+            }
+            Operation::Freeze { .. } => account.freeze(),
+            Operation::Unfreeze { .. } => account.unfreeze(),
+            Operation::Close { .. } => account.close().map_err(|err| account_error(err, line_number))?,
+            Operation::Adjust { amount, .. } => {
+                let delta = account.adjust(transaction_id, amount).map_err(|err| account_error(err, line_number))?;
+                if let Some(audit) = &mut self.audit {
+                    audit.credit(client, delta);
+                }
+            }
+            Operation::Transfer { .. } => unreachable!("transfers are applied by apply_transfer"),
+        }
+        self.record_applied(client, operation)
+    }
+
+    /// Debits `from` and credits `to`, changing neither unless both succeed.
+    fn apply_transfer(
+        &mut self,
+        from: ClientId,
+        to: ClientId,
+        operation: Operation,
+        amount: Amount,
+        line_number: u64,
+    ) -> Result<()> {
+        let transaction_id = operation.tx();
+        self.reserve_account(from, line_number)?;
+        self.reserve_account(to, line_number)?;
+        // The destination is only created once the source was debited, so a rejected transfer
+        // leaves no empty account behind
+        if let Some(destination) = self.accounts.get(&to) {
+            if destination.is_terminal() {
+                warn!(
+                    "Ignoring transfer of transaction {transaction_id} on line {line_number}: client {to} is {}",
+                    destination.status().as_str()
+                );
+                self.stats.skipped += 1;
+                return Ok(());
+            }
+            if from != to && destination.funds_available.checked_add(amount).is_none() {
+                return Err(Error::BalanceOverflow(transaction_id, line_number));
+            }
+        }
+        self.accounts
+            .entry(from)
+            .or_insert_with_key(|&client| new_account(&self.account_factory, client))
+            .transfer_out(transaction_id, amount)
+            .map_err(|err| account_error(err, line_number))?;
+        self.accounts
+            .entry(to)
+            .or_insert_with_key(|&client| new_account(&self.account_factory, client))
+            .transfer_in(transaction_id, amount)
+            .map_err(|err| account_error(err, line_number))?;
+        self.record_applied(from, operation)
+    }
+
+    /// Fails if `client` has no account yet and creating one would exceed `Settings.max_accounts`.
+    fn reserve_account(&self, client: ClientId, line_number: u64) -> Result<()> {
+        match self.settings.max_accounts {
+            Some(max_accounts) if self.accounts.len() >= max_accounts && !self.accounts.contains_key(&client) => {
+                Err(Error::AccountLimitExceeded(client, line_number, max_accounts))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Explains and journals an operation that was applied successfully.
+    fn record_applied(&mut self, client: ClientId, operation: Operation) -> Result<()> {
+        telemetry::transaction_applied(operation.kind());
+        if let Some((explained, lines)) = &mut self.explain
+            && (*explained == client || operation.destination() == Some(*explained))
+            && let Some(account) = self.accounts.get(explained)
+        {
+            lines.push(format!(
+                "{} -> available {}, held {}, {}",
+                describe(operation),
+                account.funds_available,
+                account.funds_held,
+                account.status().as_str()
+            ));
+        }
+        if operation.amount().is_some()
+            && let Some(store) = &mut self.dedupe
+        {
+            store.record(operation.tx())?;
+        }
+        if let Some(audit) = &mut self.audit {
+            audit.record(client, operation);
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.record(
+                operation.kind(),
+                client,
+                operation.tx(),
+                operation.amount(),
+                operation.destination(),
+                self.currency.as_deref(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds accounts from in-memory operations, applied in order with default settings.
+/// Errors report the 1-based position of the failing operation as their line.
+pub fn replay(operations: impl IntoIterator<Item = (ClientId, Operation)>) -> Result<HashMap<ClientId, Account>> {
+    let settings = Settings::default();
+    let mut engine = Engine::new(&settings);
+    for (position, (client, operation)) in (1..).zip(operations) {
+        engine.apply_operation(client, operation, position)?;
+    }
+    Ok(engine.into_accounts())
+}
+
+fn describe(operation: Operation) -> String {
+    if let Operation::Transfer { tx, amount, to } = operation {
+        return format!("tx {tx} transfer {amount} to client {to}");
+    }
+    match operation.amount() {
+        Some(amount) => format!("tx {} {} {amount}", operation.tx(), operation.kind().as_str()),
+        None => format!("tx {} {}", operation.tx(), operation.kind().as_str()),
+    }
+}
+
+/// The accounts in hash order, or in client id order with the `deterministic` feature, so that
+/// work done account by account, such as the warnings of `apply_fee`, repeats between runs.
+fn in_client_order(accounts: &mut HashMap<ClientId, Account>) -> impl Iterator<Item = &mut Account> {
+    let mut ordered: Vec<&mut Account> = accounts.values_mut().collect();
+    if cfg!(feature = "deterministic") {
+        ordered.sort_unstable_by_key(|account| account.client);
+    }
+    ordered.into_iter()
+}
+
+/// Like `account_error`, but reports a resolve or chargeback of a transaction that only another
+/// client has under dispute as `WrongClient`.
+fn settle_error(accounts: &HashMap<ClientId, Account>, err: AccountError, client: ClientId, line_number: u64) -> Error {
+    match err {
+        AccountError::NoDispute(tx_id)
+            if accounts.values().any(|account| account.client != client && account.is_disputed(tx_id)) =>
+        {
+            Error::WrongClient(tx_id, line_number)
+        }
+        err => account_error(err, line_number),
+    }
+}
+
+fn account_error(err: AccountError, line_number: u64) -> Error {
+    match err {
+        AccountError::NoTransaction(tx_id) => Error::NoTransaction(tx_id, line_number),
+        AccountError::NoDispute(tx_id) => Error::NoDispute(tx_id, line_number),
+        AccountError::InsufficientAvailableForDispute(tx_id) => {
+            Error::InsufficientAvailableForDispute(tx_id, line_number)
+        }
+        AccountError::Overflow(tx_id) => Error::BalanceOverflow(tx_id, line_number),
+        AccountError::InsufficientFunds(tx_id) => Error::InsufficientFunds(tx_id, line_number),
+        AccountError::AccountLocked(tx_id) => Error::AccountLocked(tx_id, line_number),
+        AccountError::CannotCloseWithHeldFunds(client) => Error::CannotCloseWithHeldFunds(client, line_number),
+        AccountError::AdjustDisputed(tx_id) => Error::AdjustDisputed(tx_id, line_number),
+    }
+}
+
+fn new_account(factory: &Option<Box<dyn Fn(ClientId) -> Account + '_>>, client: ClientId) -> Account {
+    factory.as_ref().map_or_else(|| Account::new(client), |factory| factory(client))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::write_accounts;
+
+    fn transaction(kind: TransactionType, client: ClientId, tx: u64, amount: Option<&str>) -> Transaction {
+        Transaction {
+            kind,
+            client,
+            tx,
+            amount: amount.map(|value| value.parse().expect("Failed to parse amount")),
+            dest_client: None,
+            currency: None,
+            line: tx + 1,
+        }
+    }
+
+    #[test]
+    fn test_orphan_dispute_counted() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("10.0")))
+            .expect("Deposit should succeed");
+        let result = engine.apply(&transaction(TransactionType::Dispute, 1, 42, None));
+
+        assert!(matches!(result, Err(Error::NoTransaction(42, _))));
+        assert_eq!(engine.stats().orphan_disputes, 1);
+    }
+
+    #[test]
+    fn test_repeated_dispute_is_not_orphan() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("10.0")))
+            .expect("Deposit should succeed");
+        engine
+            .apply(&transaction(TransactionType::Dispute, 1, 1, None))
+            .expect("Dispute should succeed");
+        let result = engine.apply(&transaction(TransactionType::Dispute, 1, 1, None));
+
+        assert!(matches!(result, Err(Error::NoTransaction(1, _))));
+        assert_eq!(engine.stats().orphan_disputes, 0);
+    }
+
+    #[test]
+    fn test_idempotent_dispute() {
+        let settings = Settings {
+            idempotent_dispute: true,
+            ..Default::default()
+        };
+        let mut engine = Engine::new(&settings);
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("10.0")))
+            .expect("Deposit should succeed");
+        engine
+            .apply(&transaction(TransactionType::Dispute, 1, 1, None))
+            .expect("Dispute should succeed");
+
+        engine
+            .apply(&transaction(TransactionType::Dispute, 1, 1, None))
+            .expect("Repeated dispute should be a no-op");
+
+        let account = &engine.accounts()[&1];
+        assert_eq!(account.funds_held.to_string(), "10");
+        assert_eq!(account.transaction_count(), 2);
+        assert_eq!(engine.stats().skipped, 1);
+        engine
+            .apply(&transaction(TransactionType::Resolve, 1, 1, None))
+            .expect("Resolve should succeed");
+        engine
+            .apply(&transaction(TransactionType::Dispute, 1, 1, None))
+            .expect("Dispute after resolve should succeed");
+        assert_eq!(engine.stats().skipped, 1);
+    }
+
+    #[test]
+    fn test_empty_amount_rejected_by_default() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+
+        let result = engine.apply(&transaction(TransactionType::Deposit, 1, 1, None));
+
+        assert!(matches!(result, Err(Error::MissingAmount(2))));
+    }
+
+    #[test]
+    fn test_empty_amount_as_zero() {
+        let settings = Settings {
+            empty_amount_as_zero: true,
+            ..Default::default()
+        };
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("10.0")))
+            .expect("Deposit should succeed");
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 2, None))
+            .expect("Empty deposit should succeed");
+        engine
+            .apply(&transaction(TransactionType::Withdrawal, 1, 3, None))
+            .expect("Empty withdrawal should succeed");
+
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert_eq!(account.funds_available.to_string(), "10");
+        assert_eq!(account.transaction_count(), 3);
+    }
+
+    #[test]
+    fn test_journal_resume_reproduces_accounts() {
+        let settings = Settings::default();
+        let journal_path = std::env::temp_dir().join(format!("kraken_{}_journal.csv", std::process::id()));
+        let journal_path = journal_path.to_string_lossy().into_owned();
+        let _ = std::fs::remove_file(&journal_path);
+
+        let mut engine = Engine::new(&settings);
+        engine.set_journal(Journal::open(&journal_path).expect("Failed to open journal"));
+        engine
+            .process_files(&["tests/fixtures/test_transactions.csv"])
+            .expect("Processing should succeed");
+        let expected = write_accounts(engine.into_accounts()).expect("Failed to write accounts");
+
+        let mut resumed = Engine::new(&settings);
+        let replayed = resumed.replay_journal(&journal_path).expect("Replay should succeed");
+
+        assert_eq!(replayed, 9);
+        assert_eq!(write_accounts(resumed.into_accounts()).expect("Failed to write accounts"), expected);
+    }
+
+    #[test]
+    fn test_journal_format() {
+        let settings = Settings::default();
+        let journal_path = std::env::temp_dir().join(format!("kraken_{}_journal_format.csv", std::process::id()));
+        let journal_path = journal_path.to_string_lossy().into_owned();
+        let _ = std::fs::remove_file(&journal_path);
+
+        let mut engine = Engine::new(&settings);
+        engine.set_journal(Journal::open(&journal_path).expect("Failed to open journal"));
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("10.5")))
+            .expect("Deposit should succeed");
+        engine
+            .apply(&transaction(TransactionType::Dispute, 1, 1, None))
+            .expect("Dispute should succeed");
+        assert!(engine.apply(&transaction(TransactionType::Resolve, 1, 7, None)).is_err());
+
+        let contents = std::fs::read_to_string(&journal_path).expect("Failed to read journal");
+        assert_eq!(contents, "type,client,tx,amount\ndeposit,1,1,10.5\ndispute,1,1,\n");
+    }
+
+    #[test]
+    fn test_dedupe_store_skips_reprocessed_file() {
+        let settings = Settings::default();
+        let temp_path = |name: &str| {
+            let path = std::env::temp_dir().join(format!("kraken_{}_{name}", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            path.to_string_lossy().into_owned()
+        };
+        let input = temp_path("dedupe_input.csv");
+        std::fs::write(
+            &input,
+            "type,client,tx,amount\ndeposit,1,1,10.0\ndeposit,1,2,5.0\nwithdrawal,1,3,2.0\ndispute,1,2,\nresolve,1,2,\n",
+        )
+        .expect("Failed to write fixture");
+        let (journal_path, store_path) = (temp_path("dedupe_journal.csv"), temp_path("dedupe_store"));
+
+        let mut once = Engine::new(&settings);
+        once.set_journal(Journal::open(&journal_path).expect("Failed to open journal"));
+        once.set_dedupe_store(DedupeStore::open(&store_path).expect("Failed to open dedupe store"));
+        once.process_files(&[&input]).expect("Processing should succeed");
+        let expected = write_accounts(once.into_accounts()).expect("Failed to write accounts");
+
+        let mut twice = Engine::new(&settings);
+        twice.replay_journal(&journal_path).expect("Replay should succeed");
+        twice.set_dedupe_store(DedupeStore::open(&store_path).expect("Failed to open dedupe store"));
+        twice.process_files(&[&input]).expect("Processing should succeed");
+
+        assert_eq!(twice.stats().replayed, 3);
+        assert_eq!(write_accounts(twice.into_accounts()).expect("Failed to write accounts"), expected);
+        assert_eq!(std::fs::read_to_string(&store_path).expect("Failed to read store"), "1\n2\n3\n");
+    }
+
+    fn admin_settings() -> Settings {
+        Settings {
+            allow_admin_ops: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_adjust_gated_and_audited() {
+        let default_settings = Settings::default();
+        let settings = admin_settings();
+        let mut engine = Engine::new(&settings);
+        engine.set_audit(Audit::default());
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("100.0"))),
+            Ok(transaction(TransactionType::Adjust, 1, 1, Some("120.0"))),
+            Ok(transaction(TransactionType::Deposit, 1, 2, Some("5.0"))),
+            Ok(transaction(TransactionType::Dispute, 1, 2, None)),
+        ];
+
+        let gated = Engine::new(&default_settings).apply(&transaction(TransactionType::Adjust, 1, 1, Some("1.0")));
+        engine.process(transactions).expect("Processing should succeed");
+        let rejected = engine.apply(&transaction(TransactionType::Adjust, 1, 2, Some("1.0")));
+
+        assert!(matches!(gated, Err(Error::AdminOperationNotAllowed(_))));
+        assert!(matches!(rejected, Err(Error::AdjustDisputed(2, _))));
+        let account = &engine.accounts()[&1];
+        assert_eq!(account.funds_available.to_string(), "120");
+        assert_eq!(account.funds_held.to_string(), "5");
+        assert!(engine.discrepancies().is_empty());
+    }
+
+    #[test]
+    fn test_admin_ops_disabled_by_default() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+
+        let result = engine.apply(&transaction(TransactionType::Freeze, 1, 1, None));
+
+        assert!(matches!(result, Err(Error::AdminOperationNotAllowed(2))));
+    }
+
+    #[test]
+    fn test_freeze_blocks_deposit() {
+        let settings = admin_settings();
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("10.0")))
+            .expect("Deposit should succeed");
+        engine
+            .apply(&transaction(TransactionType::Freeze, 1, 2, None))
+            .expect("Freeze should succeed");
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 3, Some("5.0")))
+            .expect("Deposit on a frozen account is skipped");
+
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert!(account.locked);
+        assert_eq!(account.funds_available.to_string(), "10");
+        assert_eq!(engine.stats().skipped, 1);
+    }
+
+    #[test]
+    fn test_unfreeze_restores_deposits() {
+        let settings = admin_settings();
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .apply(&transaction(TransactionType::Freeze, 1, 1, None))
+            .expect("Freeze should succeed");
+        engine
+            .apply(&transaction(TransactionType::Unfreeze, 1, 2, None))
+            .expect("Unfreeze should succeed");
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 3, Some("5.0")))
+            .expect("Deposit should succeed");
+
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert!(!account.locked);
+        assert_eq!(account.funds_available.to_string(), "5");
+        assert_eq!(engine.stats().skipped, 0);
+    }
+
+    #[test]
+    fn test_close_gated_and_blocks_deposits() {
+        let default_settings = Settings::default();
+        let settings = admin_settings();
+        let mut engine = Engine::new(&settings);
+
+        let gated = Engine::new(&default_settings).apply(&transaction(TransactionType::Close, 1, 1, None));
+        engine
+            .apply(&transaction(TransactionType::Close, 1, 1, None))
+            .expect("Close should succeed");
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 2, Some("5.0")))
+            .expect("Deposit should be skipped");
+
+        assert!(matches!(gated, Err(Error::AdminOperationNotAllowed(_))));
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert_eq!(account.status(), crate::account::AccountStatus::Closed);
+        assert!(account.funds_available.is_zero());
+        assert_eq!(engine.stats().skipped, 1);
+    }
+
+    #[test]
+    fn test_stop_flag_keeps_accounts_read_so_far() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        let stop = Arc::new(AtomicBool::new(false));
+        engine.set_stop_flag(Arc::clone(&stop));
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Deposit, 2, 2, Some("5.0"))),
+            Ok(transaction(TransactionType::Deposit, 1, 3, Some("7.0"))),
+        ];
+        // Interrupt once the second record has been read, as a signal handler would
+        let transactions = transactions.into_iter().enumerate().map(|(index, transaction)| {
+            if index == 1 {
+                stop.store(true, Ordering::Relaxed);
+            }
+            transaction
+        });
+
+        let result = engine.process(transactions);
+
+        assert!(matches!(result, Err(Error::Interrupted(2))));
+        assert_eq!(
+            write_accounts(engine.into_accounts()).expect("Failed to write accounts"),
+            "client,available,held,total,locked,status\n1,10,0,10,false,active\n2,5,0,5,false,active\n"
+        );
+    }
+
+    fn repeated_deposits(count: u64) -> Vec<Result<Transaction>> {
+        (1..=count)
+            .map(|tx| Ok(transaction(TransactionType::Deposit, 1, tx, Some("1.0"))))
+            .chain([Ok(transaction(TransactionType::Deposit, 2, 100, Some("1.0")))])
+            .collect()
+    }
+
+    #[test]
+    fn test_max_tx_per_client_at_limit() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        engine.set_max_tx_per_client(3, LimitAction::Reject);
+
+        engine.process(repeated_deposits(3)).expect("Transactions up to the limit should be applied");
+
+        assert_eq!(engine.accounts()[&1].transaction_count(), 3);
+    }
+
+    #[test]
+    fn test_max_tx_per_client_over_limit() {
+        let settings = Settings::default();
+        let mut rejecting = Engine::new(&settings);
+        rejecting.set_max_tx_per_client(3, LimitAction::Reject);
+        let mut warning = Engine::new(&settings);
+        warning.set_max_tx_per_client(3, LimitAction::Warn);
+
+        let rejected = rejecting.process(repeated_deposits(4));
+        warning.process(repeated_deposits(5)).expect("Transactions over the limit should only warn");
+
+        assert!(matches!(rejected, Err(Error::TransactionLimitExceeded(1, _, 3))));
+        assert_eq!(rejecting.accounts()[&1].funds_available.to_string(), "3");
+        assert_eq!(warning.accounts()[&1].funds_available.to_string(), "5");
+        assert_eq!(warning.accounts()[&2].transaction_count(), 1);
+    }
+
+    /// A deposit of the largest amount followed by one more, which overflows.
+    fn overflowing_deposits() -> Vec<Result<Transaction>> {
+        vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("922337203685477.5"))),
+            Ok(transaction(TransactionType::Deposit, 1, 2, Some("1.0"))),
+        ]
+    }
+
+    fn overflow_settings(on_overflow: OverflowMode) -> Settings {
+        Settings {
+            on_overflow,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_on_overflow_error() {
+        let settings = overflow_settings(OverflowMode::Error);
+        let mut engine = Engine::new(&settings);
+
+        let result = engine.process(overflowing_deposits());
+
+        assert!(matches!(result, Err(Error::BalanceOverflow(2, _))));
+        assert_eq!(engine.accounts()[&1].funds_available.to_string(), "922337203685477.5");
+    }
+
+    #[test]
+    fn test_on_overflow_saturate() {
+        let settings = overflow_settings(OverflowMode::Saturate);
+        let mut engine = Engine::new(&settings);
+
+        engine.process(overflowing_deposits()).expect("Overflow should saturate");
+
+        assert_eq!(engine.accounts()[&1].funds_available, Amount::MAX);
+        assert_eq!(engine.accounts()[&1].transaction_count(), 2);
+        assert_eq!(engine.stats().skipped, 0);
+    }
+
+    #[test]
+    fn test_on_overflow_skip() {
+        let settings = overflow_settings(OverflowMode::Skip);
+        let mut engine = Engine::new(&settings);
+
+        engine.process(overflowing_deposits()).expect("Overflow should be skipped");
+
+        assert_eq!(engine.accounts()[&1].funds_available.to_string(), "922337203685477.5");
+        assert_eq!(engine.accounts()[&1].transaction_count(), 1);
+        assert_eq!(engine.stats().skipped, 1);
+    }
+
+    fn seeds() -> Vec<Account> {
+        let input = std::fs::read("tests/fixtures/seed_accounts.csv").expect("Failed to read fixture");
+        crate::reader::read_seed_accounts(&input[..]).expect("Failed to read seed accounts")
+    }
+
+    #[test]
+    fn test_seed_accounts_then_deposit() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        engine.set_audit(Audit::default());
+
+        engine.seed_accounts(seeds(), SeedMode::Overwrite).expect("Seeding should succeed");
+        engine
+            .process(vec![
+                Ok(transaction(TransactionType::Deposit, 1, 1, Some("5.0"))),
+                Ok(transaction(TransactionType::Deposit, 2, 2, Some("5.0"))),
+            ])
+            .expect("Processing should succeed");
+
+        assert_eq!(engine.accounts()[&1].funds_available.to_string(), "105");
+        assert_eq!(engine.accounts()[&2].funds_available.to_string(), "5");
+        assert!(engine.accounts()[&2].locked);
+        assert!(engine.discrepancies().is_empty());
+    }
+
+    #[test]
+    fn test_journal_resume_keeps_currencies() {
+        let settings = Settings {
+            multi_currency: true,
+            ..Default::default()
+        };
+        let journal_path = std::env::temp_dir().join(format!("kraken_{}_currency_journal.csv", std::process::id()));
+        let journal_path = journal_path.to_string_lossy().into_owned();
+        let _ = std::fs::remove_file(&journal_path);
+        let deposit = |tx: u64, amount: &str, currency: &str| Transaction {
+            currency: Some(currency.to_string()),
+            ..transaction(TransactionType::Deposit, 1, tx, Some(amount))
+        };
+
+        let mut engine = Engine::new(&settings);
+        engine.set_journal(Journal::open(&journal_path).expect("Failed to open journal"));
+        engine.apply(&deposit(1, "10.0", "USD")).expect("Deposit should succeed");
+        let mut resumed = Engine::new(&settings);
+        resumed.replay_journal(&journal_path).expect("Replay should succeed");
+        resumed.apply(&deposit(2, "5.0", "EUR")).expect("Deposit should succeed");
+        let seeded = resumed.seed_accounts(seeds(), SeedMode::Overwrite);
+
+        let currencies = resumed.currency_accounts();
+        assert_eq!(currencies["USD"][&1].funds_available.to_string(), "10");
+        assert_eq!(currencies["EUR"][&1].funds_available.to_string(), "5");
+        assert!(resumed.accounts().is_empty());
+        assert!(matches!(seeded, Err(Error::UnsupportedWithMultiCurrency(_))));
+    }
+
+    #[test]
+    fn test_seeded_held_funds_are_not_drift() {
+        let settings = Settings::default();
+        let seed = || {
+            let input = b"client,available,held,locked\n1,10,5,false\n";
+            crate::reader::read_seed_accounts(&input[..]).expect("Failed to read seed accounts")
+        };
+        let mut overwritten = Engine::new(&settings);
+        let mut summed = Engine::new(&settings);
+        for engine in [&mut overwritten, &mut summed] {
+            engine
+                .process(vec![
+                    Ok(transaction(TransactionType::Deposit, 1, 1, Some("2.0"))),
+                    Ok(transaction(TransactionType::Dispute, 1, 1, None)),
+                ])
+                .expect("Processing should succeed");
+        }
+
+        overwritten.seed_accounts(seed(), SeedMode::Overwrite).expect("Seeding should succeed");
+        summed.seed_accounts(seed(), SeedMode::Sum).expect("Seeding should succeed");
+        summed
+            .apply(&transaction(TransactionType::Resolve, 1, 1, None))
+            .expect("Resolve should succeed");
+
+        assert_eq!(overwritten.accounts()[&1].funds_held.to_string(), "5");
+        assert_eq!(summed.accounts()[&1].funds_held.to_string(), "5");
+        assert!(overwritten.held_drift().is_empty());
+        assert!(summed.held_drift().is_empty());
+    }
+
+    #[test]
+    fn test_seed_accounts_collision() {
+        let settings = Settings::default();
+        let mut overwritten = Engine::new(&settings);
+        let mut summed = Engine::new(&settings);
+        for engine in [&mut overwritten, &mut summed] {
+            engine
+                .apply(&transaction(TransactionType::Deposit, 1, 1, Some("7.0")))
+                .expect("Deposit should succeed");
+        }
+
+        overwritten.seed_accounts(seeds(), SeedMode::Overwrite).expect("Seeding should succeed");
+        summed.seed_accounts(seeds(), SeedMode::Sum).expect("Seeding should succeed");
+
+        assert_eq!(overwritten.accounts()[&1].funds_available.to_string(), "100");
+        assert_eq!(summed.accounts()[&1].funds_available.to_string(), "107");
+        assert_eq!(summed.accounts()[&1].transaction_count(), 1);
+    }
+
+    #[test]
+    fn test_snapshot_every() {
+        let dir = std::env::temp_dir().join(format!("kraken_{}_snapshots", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Failed to create snapshot directory");
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        engine.set_snapshot_every(5, &dir, OutputOptions::default());
+        let transactions: Vec<_> = (1..=10)
+            .map(|tx| Ok(transaction(TransactionType::Deposit, (tx % 2 + 1) as ClientId, tx, Some("1.5"))))
+            .collect();
+
+        engine.process(transactions).expect("Processing should succeed");
+
+        let mut files: Vec<_> = std::fs::read_dir(&dir)
+            .expect("Failed to list snapshots")
+            .map(|entry| entry.expect("Failed to read entry").file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["snapshot-10.csv", "snapshot-5.csv"]);
+        let first = std::fs::read_to_string(dir.join("snapshot-5.csv")).expect("Failed to read snapshot");
+        let second = std::fs::read_to_string(dir.join("snapshot-10.csv")).expect("Failed to read snapshot");
+        std::fs::remove_dir_all(&dir).expect("Failed to remove snapshots");
+        assert_eq!(
+            first,
+            "client,available,held,total,locked,status\n1,3,0,3,false,active\n2,4.5,0,4.5,false,active\n"
+        );
+        assert_eq!(
+            second,
+            "client,available,held,total,locked,status\n1,7.5,0,7.5,false,active\n2,7.5,0,7.5,false,active\n"
+        );
+    }
+
+    #[test]
+    fn test_stats_count_records() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Deposit, 2, 2, Some("5.0"))),
+        ];
+
+        let records_read = engine.process(transactions).expect("Processing should succeed");
+
+        assert_eq!(records_read, 2);
+        assert_eq!(engine.stats().records, 2);
+        assert_eq!(engine.accounts().len(), 2);
+    }
+
+    #[test]
+    fn test_failed_processing_keeps_valid_prefix() {
+        let settings = Settings::default();
+        let input = std::fs::read_to_string("tests/fixtures/broken_midway.csv").expect("Failed to read fixture");
+        let prefix: String = input.lines().take(4).map(|line| format!("{line}\n")).collect();
+        let mut engine = Engine::new(&settings);
+
+        let result = engine.process_stream(input.as_bytes(), "broken_midway.csv");
+
+        assert!(matches!(result.as_ref().map_err(Error::unlocated), Err(Error::Parse(_))));
+        assert_eq!(engine.stats().records, 4);
+        let expected = crate::reader::parse_bytes(prefix.as_bytes(), &settings).expect("Prefix should process");
+        assert_eq!(
+            write_accounts(engine.into_accounts()).expect("Failed to write accounts"),
+            write_accounts(expected).expect("Failed to write accounts")
+        );
+    }
+
+    #[test]
+    fn test_dispute_captures_record_index() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Deposit, 1, 2, Some("5.0"))),
+            Ok(transaction(TransactionType::Dispute, 1, 1, None)),
+            Ok(transaction(TransactionType::Deposit, 1, 3, Some("1.0"))),
+            Ok(transaction(TransactionType::Dispute, 1, 2, None)),
+        ];
+
+        engine.process(transactions).expect("Processing should succeed");
+
+        let account = &engine.accounts()[&1];
+        assert_eq!(account.held_since(1), Some(3));
+        assert_eq!(account.held_since(2), Some(5));
+        assert_eq!(account.held_since(3), None);
+        assert_eq!(engine.stats().records - account.held_since(1).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_since_tx_skips_applied_movements() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        engine.set_since_tx(2);
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Withdrawal, 1, 2, Some("4.0"))),
+            Ok(transaction(TransactionType::Deposit, 1, 3, Some("5.0"))),
+            Ok(transaction(TransactionType::Dispute, 1, 1, None)),
+            Ok(transaction(TransactionType::Dispute, 1, 3, None)),
+        ];
+
+        engine.process(transactions).expect("Processing should succeed");
+
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert!(account.funds_available.is_zero());
+        assert_eq!(account.funds_held.to_string(), "5");
+        assert_eq!(engine.stats().replayed, 3);
+    }
+
+    #[test]
+    fn test_since_tx_disputes_restored_movement() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("10.0")))
+            .expect("Deposit should succeed");
+        engine.set_since_tx(1);
+
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("10.0")))
+            .expect("Deposit should be skipped");
+        engine
+            .apply(&transaction(TransactionType::Dispute, 1, 1, None))
+            .expect("Dispute should succeed");
+
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert!(account.funds_available.is_zero());
+        assert_eq!(account.funds_held.to_string(), "10");
+        assert!(account.is_disputed(1));
+        assert_eq!(engine.stats().replayed, 1);
+    }
+
+    #[test]
+    fn test_duplicate_resolve_skipped_returns_funds_once() {
+        let settings = Settings {
+            skip_invalid_rows: true,
+            ..Default::default()
+        };
+        let mut engine = Engine::new(&settings);
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Withdrawal, 1, 2, Some("4.0"))),
+            Ok(transaction(TransactionType::Dispute, 1, 1, None)),
+            Ok(transaction(TransactionType::Resolve, 1, 1, None)),
+            Ok(transaction(TransactionType::Resolve, 1, 1, None)),
+        ];
+
+        engine.process(transactions).expect("Duplicate resolve should be skipped");
+
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert_eq!(account.funds_available.to_string(), "6");
+        assert!(account.funds_held.is_zero());
+        assert_eq!(engine.stats().errors, 1);
+    }
+
+    #[test]
+    fn test_replayed_resolve_returns_funds_once() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        let transactions = || {
+            vec![
+                Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+                Ok(transaction(TransactionType::Dispute, 1, 1, None)),
+                Ok(transaction(TransactionType::Resolve, 1, 1, None)),
+            ]
+        };
+        engine.process(transactions()).expect("Processing should succeed");
+        engine.set_since_tx(1);
+
+        engine.process(transactions()).expect("Replayed rows should be skipped");
+
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert_eq!(account.funds_available.to_string(), "10");
+        assert!(account.funds_held.is_zero());
+        assert!(!account.is_disputed(1));
+    }
+
+    #[test]
+    fn test_dispute_of_unknown_client_creates_account_by_default() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+
+        let result = engine.apply(&transaction(TransactionType::Dispute, 7, 1, None));
+
+        assert!(matches!(result, Err(Error::NoTransaction(1, _))), "{:?}", result.err());
+        assert!(engine.accounts().get(&7).is_some_and(|account| account.funds_available.is_zero()));
+    }
+
+    #[test]
+    fn test_require_prior_activity() {
+        let settings = Settings {
+            require_prior_activity: true,
+            ..Default::default()
+        };
+        let mut engine = Engine::new(&settings);
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("10.0")))
+            .expect("Deposit should succeed");
+
+        let results = [TransactionType::Dispute, TransactionType::Resolve, TransactionType::Chargeback]
+            .map(|kind| engine.apply(&transaction(kind, 7, 1, None)));
+        engine
+            .apply(&transaction(TransactionType::Dispute, 1, 1, None))
+            .expect("Dispute of a known client should succeed");
+
+        for result in results {
+            assert!(matches!(result, Err(Error::UnknownClient(7, 2))), "{:?}", result.err());
+        }
+        assert!(!engine.accounts().contains_key(&7));
+        assert_eq!(engine.accounts()[&1].funds_held.to_string(), "10");
+    }
+
+    #[test]
+    fn test_apply_fee() {
+        let settings = admin_settings();
+        let mut engine = Engine::new(&settings);
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Deposit, 2, 2, Some("0.5"))),
+            Ok(transaction(TransactionType::Deposit, 3, 3, Some("10.0"))),
+            Ok(transaction(TransactionType::Freeze, 3, 4, None)),
+            Ok(transaction(TransactionType::Deposit, 4, 5, Some("1.0"))),
+        ];
+        engine.process(transactions).expect("Processing should succeed");
+
+        let charged = engine.apply_fee("1.0".parse().expect("Failed to parse amount"));
+
+        assert_eq!(charged, 2);
+        let available = |client: ClientId| engine.accounts()[&client].funds_available.to_string();
+        assert_eq!(available(1), "9");
+        assert_eq!(available(2), "0.5");
+        assert_eq!(available(3), "10");
+        assert_eq!(available(4), "0");
+    }
+
+    #[test]
+    fn test_audit_holds_after_chargeback() {
+        let settings = Settings {
+            dispute_policy: crate::account::DisputePolicy::Clamp,
+            allow_transfers: true,
+            ..Default::default()
+        };
+        let mut engine = Engine::new(&settings);
+        engine.set_audit(Audit::default());
+        let mut transfer = transaction(TransactionType::Transfer, 2, 5, Some("1.0"));
+        transfer.dest_client = Some(3);
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Withdrawal, 1, 2, Some("4.0"))),
+            Ok(transaction(TransactionType::Deposit, 2, 3, Some("5.0"))),
+            Ok(transaction(TransactionType::Dispute, 1, 1, None)),
+            Ok(transaction(TransactionType::Chargeback, 1, 1, None)),
+            Ok(transaction(TransactionType::Deposit, 2, 4, Some("2.0"))),
+            Ok(transfer),
+        ];
+
+        engine.process(transactions).expect("Processing should succeed");
+        engine.apply_fee("0.5".parse().expect("Failed to parse amount"));
+
+        let account = &engine.accounts()[&1];
+        assert!(account.locked);
+        assert!(account.funds_available.is_zero());
+        assert!(account.funds_held.is_zero());
+        assert_eq!(engine.accounts()[&2].funds_available.to_string(), "5.5");
+        assert_eq!(engine.accounts()[&3].funds_available.to_string(), "0.5");
+        assert!(engine.discrepancies().is_empty());
+    }
+
+    #[test]
+    fn test_audit_holds_after_withdrawal_chargeback() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        engine.set_audit(Audit::default());
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Withdrawal, 1, 2, Some("4.0"))),
+            Ok(transaction(TransactionType::Dispute, 1, 2, None)),
+            Ok(transaction(TransactionType::Chargeback, 1, 2, None)),
+        ];
+
+        engine.process(transactions).expect("Processing should succeed");
+
+        let account = &engine.accounts()[&1];
+        assert!(account.locked);
+        assert_eq!(account.funds_available.to_string(), "10");
+        assert!(account.funds_held.is_zero());
+        assert!(engine.discrepancies().is_empty());
+    }
+
+    #[cfg(feature = "deterministic")]
+    #[test]
+    fn test_deterministic_account_order() {
+        let mut accounts: HashMap<ClientId, Account> = (1..=64).rev().map(|client| (client, Account::new(client))).collect();
+
+        let clients: Vec<ClientId> = in_client_order(&mut accounts).map(|account| account.client).collect();
+
+        assert_eq!(clients, (1..=64).collect::<Vec<ClientId>>());
+    }
+
+    #[test]
+    fn test_audit_reports_discrepancy() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        engine.set_audit(Audit::default());
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Deposit, 2, 2, Some("5.0"))),
+        ];
+        engine.process(transactions).expect("Processing should succeed");
+
+        engine.accounts.get_mut(&2).expect("Client 2 should exist").funds_available = "6.0".parse().unwrap();
+
+        let discrepancies = engine.discrepancies();
+        assert_eq!(discrepancies.len(), 1);
+        assert_eq!(discrepancies[0].client, 2);
+        assert_eq!(discrepancies[0].net.to_string(), "5");
+        assert_eq!(discrepancies[0].total.to_string(), "6");
+    }
+
+    #[test]
+    fn test_check_hold_ratio() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Dispute, 1, 1, None)),
+            Ok(transaction(TransactionType::Deposit, 2, 2, Some("10.0"))),
+            Ok(transaction(TransactionType::Deposit, 3, 3, Some("5.0"))),
+            Ok(transaction(TransactionType::Withdrawal, 3, 4, Some("5.0"))),
+        ];
+        engine.process(transactions).expect("Processing should succeed");
+
+        assert_eq!(engine.check_hold_ratio(0.5), vec![1]);
+        assert_eq!(engine.check_hold_ratio(0.0), vec![1]);
+        assert!(engine.check_hold_ratio(1.0).is_empty());
+    }
+
+    #[test]
+    fn test_resolve_by_wrong_client() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("10.0")))
+            .expect("Deposit should succeed");
+        engine
+            .apply(&transaction(TransactionType::Dispute, 1, 1, None))
+            .expect("Dispute should succeed");
+
+        let resolve = engine.apply(&transaction(TransactionType::Resolve, 2, 1, None));
+        let chargeback = engine.apply(&transaction(TransactionType::Chargeback, 2, 1, None));
+
+        assert!(matches!(resolve, Err(Error::WrongClient(1, _))), "{:?}", resolve.err());
+        assert!(matches!(chargeback, Err(Error::WrongClient(1, _))), "{:?}", chargeback.err());
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert!(account.is_disputed(1));
+        assert_eq!(account.funds_held.to_string(), "10");
+        assert!(!account.locked);
+    }
+
+    #[test]
+    fn test_colliding_dispute_ids_settle_own_dispute() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        for client in [1, 2] {
+            engine
+                .apply(&transaction(TransactionType::Deposit, client, 1, Some("10.0")))
+                .expect("Deposit should succeed");
+            engine
+                .apply(&transaction(TransactionType::Dispute, client, 1, None))
+                .expect("Dispute should succeed");
+        }
+
+        engine
+            .apply(&transaction(TransactionType::Resolve, 2, 1, None))
+            .expect("Resolve should succeed");
+        let repeated = engine.apply(&transaction(TransactionType::Resolve, 2, 1, None));
+
+        assert!(matches!(repeated, Err(Error::WrongClient(1, _))), "{:?}", repeated.err());
+        assert!(engine.accounts()[&1].is_disputed(1));
+        assert_eq!(engine.accounts()[&1].funds_held.to_string(), "10");
+        assert!(!engine.accounts()[&2].is_disputed(1));
+        assert_eq!(engine.accounts()[&2].funds_available.to_string(), "10");
+    }
+
+    #[test]
+    fn test_replay_operations() {
+        let amount = |value: &str| value.parse::<Amount>().expect("Failed to parse amount");
+        let operations = vec![
+            (1, Operation::Deposit { tx: 1, amount: amount("10.0") }),
+            (2, Operation::Deposit { tx: 2, amount: amount("4.0") }),
+            (1, Operation::Withdrawal { tx: 3, amount: amount("2.5") }),
+            (2, Operation::Dispute { tx: 2 }),
+            (2, Operation::Chargeback { tx: 2 }),
+        ];
+
+        let accounts = replay(operations).expect("Replay should succeed");
+
+        let first = accounts.get(&1).expect("Client 1 should exist");
+        assert_eq!(first.funds_available.to_string(), "7.5");
+        let second = accounts.get(&2).expect("Client 2 should exist");
+        assert!(second.funds_available.is_zero());
+        assert!(second.funds_held.is_zero());
+        assert!(second.locked);
+    }
+
+    #[test]
+    fn test_replay_reports_failing_position() {
+        let result = replay([(1, Operation::Resolve { tx: 7 })]);
+
+        assert!(matches!(result, Err(Error::NoDispute(7, 1))));
+    }
+
+    #[test]
+    fn test_timing_display() {
+        let timing = Timing {
+            parsing: Duration::from_micros(12_345),
+            serialization: Duration::from_millis(2),
+        };
+
+        assert_eq!(timing.to_string(), "parsing: 12.345 ms, serialization: 2.000 ms");
+    }
+
+    #[test]
+    fn test_max_transaction_amount() {
+        let settings = Settings {
+            max_transaction_amount: Some("100.0".parse().expect("Failed to parse amount")),
+            ..Default::default()
+        };
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("100.0")))
+            .expect("Deposit at the limit should succeed");
+        let result = engine.apply(&transaction(TransactionType::Withdrawal, 1, 2, Some("100.0001")));
+
+        assert!(matches!(result, Err(Error::AmountTooLarge(3))));
+        assert_eq!(engine.accounts().get(&1).expect("Client 1 should exist").funds_available.to_string(), "100");
+    }
+
+    #[test]
+    fn test_explain_client() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+        engine.set_explain(1);
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Deposit, 2, 2, Some("3.0"))),
+            Ok(transaction(TransactionType::Dispute, 1, 1, None)),
+            Ok(transaction(TransactionType::Chargeback, 1, 1, None)),
+            Ok(transaction(TransactionType::Deposit, 1, 3, Some("1.0"))),
+        ];
+
+        engine.process(transactions).expect("Processing should succeed");
+
+        assert_eq!(
+            engine.explanation(),
+            [
+                "tx 1 deposit 10 -> available 10, held 0, active",
+                "tx 1 dispute -> available 0, held 10, active",
+                "tx 1 chargeback -> available 0, held 0, frozen",
+                "tx 3 deposit 1 ignored, account is frozen",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_records_per_second() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .process_files(&["tests/fixtures/test_transactions.csv"])
+            .expect("Processing should succeed");
+
+        assert!(engine.stats().processing > Duration::ZERO);
+        assert!(engine.stats().records_per_second() > 0.0);
+        assert!(engine.stats().to_string().contains("records/s: "));
+        assert_eq!(Stats::default().records_per_second(), 0.0);
+    }
+
+    fn dispute_after_lock() -> Vec<Result<Transaction>> {
+        vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Deposit, 1, 2, Some("5.0"))),
+            Ok(transaction(TransactionType::Dispute, 1, 1, None)),
+            Ok(transaction(TransactionType::Chargeback, 1, 1, None)),
+            Ok(transaction(TransactionType::Dispute, 1, 2, None)),
+            Ok(transaction(TransactionType::Chargeback, 1, 2, None)),
+        ]
+    }
+
+    #[test]
+    fn test_dispute_after_chargeback_lock_ignored() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+
+        engine.process(dispute_after_lock()).expect("Disputes on a locked account should be ignored");
+
+        assert_eq!(engine.stats().skipped, 2);
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert!(account.locked);
+        assert!(!account.is_disputed(2));
+        assert_eq!(account.funds_available.to_string(), "5");
+        assert!(account.funds_held.is_zero());
+    }
+
+    #[test]
+    fn test_dispute_after_chargeback_lock_rejected() {
+        let settings = Settings {
+            reject_disputes_on_locked: true,
+            ..Default::default()
+        };
+        let mut engine = Engine::new(&settings);
+
+        let result = engine.process(dispute_after_lock());
+
+        assert!(matches!(result, Err(Error::AccountLocked(2, _))));
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert!(!account.is_disputed(2));
+        assert_eq!(account.funds_available.to_string(), "5");
+    }
+
+    #[test]
+    fn test_deprecated_ignore_chargeback_on_locked() {
+        let rejecting_settings = Settings {
+            ignore_chargeback_on_locked: Some(false),
+            ..Default::default()
+        };
+        let ignoring_settings = Settings {
+            ignore_chargeback_on_locked: Some(true),
+            ..Default::default()
+        };
+        let mut ignoring = Engine::new(&ignoring_settings);
+
+        let rejected = Engine::new(&rejecting_settings).process(dispute_after_lock());
+        ignoring.process(dispute_after_lock()).expect("Disputes on a locked account should be ignored");
+
+        assert!(matches!(rejected, Err(Error::AccountLocked(2, _))));
+        assert_eq!(ignoring.stats().skipped, 2);
+    }
+
+    #[test]
+    fn test_resolve_on_frozen_account_ignored_until_unfrozen() {
+        let settings = Settings {
+            allow_admin_ops: true,
+            ..Default::default()
+        };
+        let mut engine = Engine::new(&settings);
+        let transactions = vec![
+            Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0"))),
+            Ok(transaction(TransactionType::Dispute, 1, 1, None)),
+            Ok(transaction(TransactionType::Freeze, 1, 2, None)),
+            Ok(transaction(TransactionType::Resolve, 1, 1, None)),
+            Ok(transaction(TransactionType::Unfreeze, 1, 3, None)),
+            Ok(transaction(TransactionType::Resolve, 1, 1, None)),
+        ];
+
+        engine.process(transactions).expect("Processing should succeed");
+
+        assert_eq!(engine.stats().skipped, 1);
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert!(!account.is_disputed(1));
+        assert_eq!(account.funds_available.to_string(), "10");
+    }
+
+    fn bad_rows() -> Vec<Result<Transaction>> {
+        let mut transactions = vec![Ok(transaction(TransactionType::Deposit, 1, 1, Some("10.0")))];
+        transactions.extend((2..=10).map(|line| Err(Error::UnknownTransactionType(line))));
+        transactions.push(Ok(transaction(TransactionType::Deposit, 1, 11, Some("5.0"))));
+        transactions
+    }
+
+    #[test]
+    fn test_skip_invalid_rows() {
+        let settings = Settings {
+            skip_invalid_rows: true,
+            ..Default::default()
+        };
+        let mut engine = Engine::new(&settings);
+
+        engine.process(bad_rows()).expect("Invalid rows should be skipped");
+
+        assert_eq!(engine.stats().errors, 9);
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert_eq!(account.funds_available.to_string(), "15");
+    }
+
+    #[test]
+    fn test_max_errors_aborts() {
+        let settings = Settings {
+            skip_invalid_rows: true,
+            max_errors: Some(3),
+            ..Default::default()
+        };
+        let mut engine = Engine::new(&settings);
+
+        let result = engine.process(bad_rows());
+
+        assert!(matches!(result, Err(Error::TooManyErrors(4))));
+        assert_eq!(engine.stats().records, 5);
+        assert_eq!(engine.failed_line(), Some(5));
+    }
+
+    #[test]
+    fn test_invalid_row_fails_by_default() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+
+        let result = engine.process(bad_rows());
+
+        assert!(matches!(result, Err(Error::UnknownTransactionType(2))));
+        assert_eq!(engine.failed_line(), Some(2));
+    }
+
+    fn transfer(client: ClientId, tx: u64, amount: &str, dest_client: ClientId) -> Transaction {
+        Transaction {
+            dest_client: Some(dest_client),
+            ..transaction(TransactionType::Transfer, client, tx, Some(amount))
+        }
+    }
+
+    fn transfer_settings() -> Settings {
+        Settings {
+            allow_transfers: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_transfer_moves_funds() {
+        let settings = transfer_settings();
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("10.0")))
+            .expect("Deposit should succeed");
+        engine.apply(&transfer(1, 2, "4.5", 2)).expect("Transfer should succeed");
+
+        let source = engine.accounts().get(&1).expect("Client 1 should exist");
+        let destination = engine.accounts().get(&2).expect("Client 2 should exist");
+        assert_eq!(source.funds_available.to_string(), "5.5");
+        assert_eq!(destination.funds_available.to_string(), "4.5");
+        assert!(matches!(
+            engine.apply(&transaction(TransactionType::Dispute, 1, 2, None)),
+            Err(Error::NoTransaction(2, _))
+        ));
+    }
+
+    #[test]
+    fn test_transfer_insufficient_funds() {
+        let settings = transfer_settings();
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .apply(&transaction(TransactionType::Deposit, 1, 1, Some("10.0")))
+            .expect("Deposit should succeed");
+        let result = engine.apply(&transfer(1, 2, "10.0001", 2));
+
+        assert!(matches!(result, Err(Error::InsufficientFunds(2, _))));
+        assert_eq!(engine.accounts().get(&1).expect("Client 1 should exist").funds_available.to_string(), "10");
+        assert!(engine.accounts().get(&2).is_none());
+    }
+
+    #[test]
+    fn test_rejected_transfer_creates_no_account() {
+        let settings = Settings {
+            max_accounts: Some(2),
+            skip_invalid_rows: true,
+            ..transfer_settings()
+        };
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .process(vec![
+                Ok(transaction(TransactionType::Deposit, 1, 1, Some("1.0"))),
+                Ok(transfer(1, 2, "5.0", 9)),
+                Ok(transaction(TransactionType::Deposit, 3, 3, Some("1.0"))),
+            ])
+            .expect("Rejected transfer should be skipped");
+        let beyond_limit = engine.apply(&transfer(4, 4, "1.0", 1));
+
+        assert_eq!(engine.stats().errors, 1);
+        assert!(!engine.accounts().contains_key(&9));
+        assert_eq!(engine.accounts()[&3].funds_available.to_string(), "1");
+        assert!(matches!(beyond_limit, Err(Error::AccountLimitExceeded(4, _, 2))));
+    }
+
+    #[test]
+    fn test_transfer_gated() {
+        let settings = Settings::default();
+        let mut engine = Engine::new(&settings);
+
+        let gated = engine.apply(&transfer(1, 1, "1.0", 2));
+        let missing = Engine::new(&transfer_settings())
+            .apply(&transaction(TransactionType::Transfer, 1, 1, Some("1.0")));
+
+        assert!(matches!(gated, Err(Error::TransferNotAllowed(_))));
+        assert!(matches!(missing, Err(Error::MissingDestinationClient(_))));
+    }
+}
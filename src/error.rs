@@ -1,4 +1,5 @@
 use std::str::Utf8Error;
+use crate::ClientId;
 use primitive_fixed_point_decimal::ParseError;
 
 #[derive(thiserror::Error, Debug)]
@@ -14,22 +15,219 @@ pub enum Error {
     Parse(#[from] ParseError),
     #[error(transparent)]
     LexicalParse(#[from] lexical_core::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
 
     // User errors
+    #[error("Duplicate header column {0}")]
+    DuplicateHeader(String),
+    #[error("Header {0} doesn't match the expected columns")]
+    UnexpectedHeader(String),
     #[error("Missing transaction type on line {0}")]
     MissingTransactionType(u64),
     #[error("Missing client on line {0}")]
     MissingClient(u64),
+    #[error("Empty or invalid client on line {0}")]
+    InvalidClient(u64),
+    #[error("Client id {0} on line {1} exceeds the configured maximum")]
+    ClientOutOfRange(u64, u64),
     #[error("Missing transaction id on line {0}")]
     MissingTransactionId(u64),
+    #[error("Invalid transaction id on line {0}")]
+    InvalidTransactionId(u64),
     #[error("Amount missing on line {0}")]
     MissingAmount(u64),
+    #[error("Currency missing on line {0}")]
+    MissingCurrency(u64),
+    #[error("Amount on line {0} exceeds the per-transaction maximum")]
+    AmountTooLarge(u64),
+    #[error("Amount field on line {0} is too long")]
+    AmountTooLong(u64),
     #[error("Negative amount on line {0}")]
     NegativeAmount(u64),
+    #[error("Amount on line {0} is not in canonical form")]
+    NonCanonicalAmount(u64),
     #[error("Unknown transaction type on line {0}")]
     UnknownTransactionType(u64),
+    #[error("Administrative operation on line {0} is not allowed")]
+    AdminOperationNotAllowed(u64),
+    #[error("Transfer on line {0} is not allowed")]
+    TransferNotAllowed(u64),
+    #[error("Transfer on line {0} has no destination client")]
+    MissingDestinationClient(u64),
+    #[error("Available funds do not cover the transfer of transaction id {0} on line {1}")]
+    InsufficientFunds(u64, u64),
+    #[error("Client {0} on line {1} has no earlier transactions")]
+    UnknownClient(ClientId, u64),
     #[error("Transaction id {0} not found for dispute on line {1}")]
     NoTransaction(u64, u64),
     #[error("Dispute not found for resolve/chargeback of transaction id {0} on line {1}")]
     NoDispute(u64, u64),
+    #[error("Transaction id {0} on line {1} is disputed by a different client")]
+    WrongClient(u64, u64),
+    #[error("Available funds do not cover the dispute of transaction id {0} on line {1}")]
+    InsufficientAvailableForDispute(u64, u64),
+    #[error("Chargeback of transaction id {0} on line {1} rejected, account is locked")]
+    AccountLocked(u64, u64),
+    #[error("Client {0} cannot be closed while funds are held, on line {1}")]
+    CannotCloseWithHeldFunds(ClientId, u64),
+    #[error("Transaction id {0} on line {1} is under dispute and can't be adjusted")]
+    AdjustDisputed(u64, u64),
+    #[error("Balance overflow applying transaction id {0} on line {1}")]
+    BalanceOverflow(u64, u64),
+    #[error("Client {0} on line {1} exceeds the limit of {2} transactions")]
+    TransactionLimitExceeded(ClientId, u64, u64),
+    #[error("Client {0} on line {1} exceeds the limit of {2} accounts")]
+    AccountLimitExceeded(ClientId, u64, usize),
+    #[error("Delimiter {0:?} is not a single-byte ASCII character")]
+    InvalidDelimiter(char),
+    #[error("Comment character {0:?} is not a single-byte ASCII character")]
+    InvalidCommentChar(char),
+    #[error("Input columns don't name the {0} column")]
+    MissingInputColumn(&'static str),
+    #[error("Type alias {0} names an unknown transaction type")]
+    InvalidTypeAlias(String),
+    #[error("Currency symbol {0:?} is empty or contains digits, signs or the decimal separator")]
+    InvalidCurrencySymbol(String),
+    #[error("Fixed-width input requires the fixed_width column spans")]
+    MissingFixedWidth,
+    #[error("{0} can't be combined with multi_currency")]
+    UnsupportedWithMultiCurrency(&'static str),
+    #[error("Invalid account record {0:?}")]
+    InvalidRecord(String),
+    #[error("Invalid decimals range {0} to {1}, expected at most 4 decimals and min <= max")]
+    InvalidDecimals(usize, usize),
+    #[error("Formatted amount {0} has more than 4 decimals")]
+    PrecisionLeak(String),
+    #[error("Unknown output column {0}")]
+    UnknownColumn(String),
+    #[error("Total balance of client {0} overflows")]
+    TotalOverflow(ClientId),
+    #[error("Sum of balances over all clients overflows")]
+    TotalsOverflow,
+    #[error("Total balance of client {0} is negative")]
+    NegativeTotal(ClientId),
+    #[error("Processing exceeded the deadline of {0} ms")]
+    Timeout(u64),
+    #[error("Aborted after {0} invalid rows")]
+    TooManyErrors(u64),
+    #[error("Interrupted after {0} records")]
+    Interrupted(u64),
+    /// A row error with the line and byte offset the reader reached with its record
+    #[error("{source} ({})", location(.source, .line, .byte))]
+    Located { source: Box<Error>, line: u64, byte: u64 },
+}
+
+/// Names the line only when the error doesn't already.
+fn location(source: &Error, line: &u64, byte: &u64) -> String {
+    match source.line() {
+        Some(_) => format!("byte {byte}"),
+        None => format!("line {line}, byte {byte}"),
+    }
+}
+
+impl Error {
+    /// Errors from reading or decoding the input, as opposed to user errors in its content
+    /// such as a malformed amount.
+    pub fn is_system_error(&self) -> bool {
+        if let Error::Located { source, .. } = self {
+            return source.is_system_error();
+        }
+        matches!(self, Error::Io(_) | Error::Csv(_) | Error::Json(_) | Error::Toml(_))
+    }
+
+    /// Whether the error concerns a single input row, so processing could go on with the next one.
+    pub fn is_row_error(&self) -> bool {
+        if let Error::Located { source, .. } = self {
+            return source.is_row_error();
+        }
+        matches!(
+            self,
+            Error::Utf8(_)
+                | Error::Parse(_)
+                | Error::LexicalParse(_)
+                | Error::MissingTransactionType(_)
+                | Error::MissingClient(_)
+                | Error::InvalidClient(_)
+                | Error::ClientOutOfRange(..)
+                | Error::MissingTransactionId(_)
+                | Error::InvalidTransactionId(_)
+                | Error::MissingAmount(_)
+                | Error::MissingCurrency(_)
+                | Error::AmountTooLarge(_)
+                | Error::AmountTooLong(_)
+                | Error::NegativeAmount(_)
+                | Error::NonCanonicalAmount(_)
+                | Error::UnknownTransactionType(_)
+                | Error::AdminOperationNotAllowed(_)
+                | Error::TransferNotAllowed(_)
+                | Error::MissingDestinationClient(_)
+                | Error::InsufficientFunds(..)
+                | Error::UnknownClient(..)
+                | Error::NoTransaction(..)
+                | Error::NoDispute(..)
+                | Error::WrongClient(..)
+                | Error::InsufficientAvailableForDispute(..)
+                | Error::AccountLocked(..)
+                | Error::CannotCloseWithHeldFunds(..)
+                | Error::AdjustDisputed(..)
+                | Error::TransactionLimitExceeded(..)
+                | Error::BalanceOverflow(..)
+        )
+    }
+
+    /// Input line the error was raised on, when the error names one.
+    pub fn line(&self) -> Option<u64> {
+        match *self {
+            Error::MissingTransactionType(line)
+            | Error::MissingClient(line)
+            | Error::InvalidClient(line)
+            | Error::MissingTransactionId(line)
+            | Error::InvalidTransactionId(line)
+            | Error::MissingAmount(line)
+            | Error::MissingCurrency(line)
+            | Error::AmountTooLarge(line)
+            | Error::AmountTooLong(line)
+            | Error::NegativeAmount(line)
+            | Error::NonCanonicalAmount(line)
+            | Error::UnknownTransactionType(line)
+            | Error::AdminOperationNotAllowed(line)
+            | Error::TransferNotAllowed(line)
+            | Error::MissingDestinationClient(line)
+            | Error::ClientOutOfRange(_, line)
+            | Error::InsufficientFunds(_, line)
+            | Error::UnknownClient(_, line)
+            | Error::NoTransaction(_, line)
+            | Error::NoDispute(_, line)
+            | Error::WrongClient(_, line)
+            | Error::InsufficientAvailableForDispute(_, line)
+            | Error::AccountLocked(_, line)
+            | Error::CannotCloseWithHeldFunds(_, line)
+            | Error::AdjustDisputed(_, line)
+            | Error::BalanceOverflow(_, line)
+            | Error::TransactionLimitExceeded(_, line, _)
+            | Error::AccountLimitExceeded(_, line, _)
+            | Error::Located { line, .. } => Some(line),
+            _ => None,
+        }
+    }
+
+    /// Line and, for row errors raised while reading CSV input, byte offset the error was
+    /// raised at.
+    pub fn position(&self) -> Option<(u64, Option<u64>)> {
+        match *self {
+            Error::Located { line, byte, .. } => Some((line, Some(byte))),
+            _ => self.line().map(|line| (line, None)),
+        }
+    }
+
+    /// The error without the position `Located` adds to it.
+    pub fn unlocated(&self) -> &Error {
+        match self {
+            Error::Located { source, .. } => source.unlocated(),
+            _ => self,
+        }
+    }
 }
@@ -0,0 +1,53 @@
+use crate::prelude::*;
+use crate::reader::TransactionType;
+use crate::{Amount, ClientId};
+use csv::WriterBuilder;
+use std::fs::{File, OpenOptions};
+
+/// Append-only log of applied transactions.
+///
+/// Lines use the canonical input format `type,client,tx,amount` with a `.` decimal separator,
+/// so a journal can be replayed like any other input file. Transfers add their receiving
+/// client as a fifth field and transactions in a currency, with `Settings.multi_currency`,
+/// the currency as a sixth. Each line is flushed as it is
+/// written, so the journal survives a crash of the process.
+pub struct Journal {
+    writer: csv::Writer<File>,
+}
+
+impl Journal {
+    /// Fields of a journal line; lines end after the last field they fill in.
+    pub const COLUMNS: [&'static str; 6] = ["type", "client", "tx", "amount", "dest_client", "currency"];
+
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_new = file.metadata()?.len() == 0;
+        let mut writer = WriterBuilder::new().has_headers(false).flexible(true).from_writer(file);
+        if is_new {
+            writer.write_record(["type", "client", "tx", "amount"])?;
+            writer.flush()?;
+        }
+        Ok(Journal { writer })
+    }
+
+    pub fn record(
+        &mut self,
+        kind: TransactionType,
+        client: ClientId,
+        tx: u64,
+        amount: Option<Amount>,
+        dest_client: Option<ClientId>,
+        currency: Option<&str>,
+    ) -> Result<()> {
+        let amount = amount.map(|amount| amount.to_string()).unwrap_or_default();
+        let (client, tx) = (client.to_string(), tx.to_string());
+        let dest = dest_client.map(|dest_client| dest_client.to_string()).unwrap_or_default();
+        match (dest_client, currency) {
+            (_, Some(currency)) => self.writer.write_record([kind.as_str(), &client, &tx, &amount, &dest, currency])?,
+            (Some(_), None) => self.writer.write_record([kind.as_str(), &client, &tx, &amount, &dest])?,
+            (None, None) => self.writer.write_record([kind.as_str(), &client, &tx, &amount])?,
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+pub mod account;
+pub mod audit;
+pub mod dedupe;
+pub mod dialect;
+pub mod engine;
+pub mod error;
+pub mod journal;
+pub mod money;
+mod prelude;
+pub mod reader;
+pub mod settings;
+mod telemetry;
+pub mod writer;
+
+use primitive_fixed_point_decimal::ConstScaleFpdec;
+
+/// Monetary amount with four decimal places. Zero checks should use `Amount::ZERO`
+/// and `is_zero()` rather than comparing formatted strings.
+pub type Amount = ConstScaleFpdec<i64, 4>;
+
+/// Client identifier as it appears in the `client` column.
+#[cfg(not(feature = "wide-client"))]
+pub type ClientId = u16;
+
+/// Client identifier as it appears in the `client` column, widened by the `wide-client` feature.
+#[cfg(feature = "wide-client")]
+pub type ClientId = u32;
+
+/// Parses a decimal literal into an `Amount`, e.g. `amount!(100.50)` or `amount!(-2.5)`,
+/// panicking with the literal when it doesn't fit, such as with more than four decimals.
+#[macro_export]
+macro_rules! amount {
+    ($value:literal) => {
+        $crate::amount!(@parse stringify!($value))
+    };
+    (- $value:literal) => {
+        $crate::amount!(@parse concat!("-", stringify!($value)))
+    };
+    (@parse $literal:expr) => {
+        $literal
+            .parse::<$crate::Amount>()
+            .unwrap_or_else(|err| panic!("Invalid amount literal {}: {:?}", $literal, err))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amount_macro() {
+        assert_eq!(amount!(100.50), "100.5".parse::<Amount>().unwrap());
+        assert_eq!(amount!(-2.5), "-2.5".parse::<Amount>().unwrap());
+        assert_eq!(amount!(7), "7".parse::<Amount>().unwrap());
+        assert_eq!(amount!(0.0001).mantissa(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid amount literal 1.23456")]
+    fn test_amount_macro_rejects_excess_decimals() {
+        amount!(1.23456);
+    }
+}
@@ -0,0 +1,52 @@
+use env_logger::WriteStyle;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Forwards to env_logger and counts warnings, including ones the filter hides.
+struct CountingLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for CountingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() == Level::Warn || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() == Level::Warn {
+            WARNINGS.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs the logger; without `color` no escape codes are written, with it only when stderr
+/// is a terminal. A `level` replaces the level of `RUST_LOG` for all modules.
+pub fn init(color: bool, level: Option<LevelFilter>) {
+    let mut builder = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn"));
+    builder.write_style(if color { WriteStyle::Auto } else { WriteStyle::Never });
+    if let Some(level) = level {
+        builder.filter_level(level);
+    }
+    let inner = builder.build();
+    if !color {
+        owo_colors::set_override(false);
+    }
+    let max_level = inner.filter().max(LevelFilter::Warn);
+    if log::set_boxed_logger(Box::new(CountingLogger { inner })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Number of warnings logged so far.
+pub fn warnings() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
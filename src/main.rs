@@ -1,38 +1,244 @@
 extern crate core;
-mod account;
-mod error;
-mod prelude;
-mod reader;
-mod settings;
-
-use crate::reader::{parse_csv, write_accounts};
-use crate::settings::Settings;
+mod cli;
+mod logging;
+
+use crate::cli::Options;
+use kraken::audit::Audit;
+use kraken::dedupe::DedupeStore;
+use kraken::dialect::Dialect;
+use kraken::engine::{Engine, Timing};
+use kraken::error::Error;
+use kraken::journal::Journal;
+use kraken::reader::{expand_inputs, read_seed_accounts};
+use kraken::settings::Settings;
+use kraken::writer::{
+    parse_columns, write_accounts_to, write_currency_accounts_to, write_dispute_detail, Decimals, DigestWriter, OutputOptions,
+};
+use log::{error, info, warn};
+use owo_colors::{OwoColorize, Stream};
 use std::env;
-use primitive_fixed_point_decimal::ConstScaleFpdec;
+use std::fs::File;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
-type Amount = ConstScaleFpdec<i64, 4>;
+/// Exit code after an interrupt, following the shell convention of 128 + SIGINT.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let program = args.first().expect("program name not available");
-    if args.len() != 2 {
-        eprintln!("Usage: {program} <csv file>");
+    let mut args = env::args();
+    let program = args.next().expect("program name not available");
+    let mut options = Options::parse(args).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        eprintln!("Usage: {program} {}", cli::USAGE);
         std::process::exit(1);
-    }
+    });
+    logging::init(options.color, options.log_level());
+    let color = options.color;
 
-    let settings = Settings::new().unwrap_or_else(|err| {
-        eprintln!("Warning: Failed to load settings: {err}. Using defaults.");
+    let mut settings = Settings::new().unwrap_or_else(|err| {
+        warn!("Failed to load settings: {err}. Using defaults.");
         Settings::default()
     });
+    if let Some(path) = &options.dialect {
+        let dialect = Dialect::load(path).unwrap_or_else(|err| {
+            error!("{}", describe(&err, color));
+            std::process::exit(1);
+        });
+        dialect.apply(&mut settings);
+    }
+    options.output.terminator = settings.output_terminator;
+    options.output.flush_every = settings.flush_every;
+    options.output.decimals = Decimals::new(settings.min_decimals, settings.max_decimals).unwrap_or_else(|err| {
+        error!("{}", describe(&err, color));
+        std::process::exit(1);
+    });
+    if let Some(names) = &settings.output_columns {
+        options.output.columns = Some(parse_columns(names).unwrap_or_else(|err| {
+            error!("{}", describe(&err, color));
+            std::process::exit(1);
+        }));
+    }
 
-    parse_csv(args.get(1).expect("csv file argument"), settings.buffer_capacity())
-        .and_then(|accounts| {
-            write_accounts(accounts).map(|output| {
-                print!("{}", output);
-            })
+    let inputs = expand_inputs(&options.inputs).unwrap_or_else(|err| {
+        error!("{}", describe(&err, color));
+        std::process::exit(1);
+    });
+    let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+    let mut engine = Engine::new(&settings);
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = Arc::clone(&stop);
+    if let Err(err) = ctrlc::set_handler(move || handler_stop.store(true, Ordering::Relaxed)) {
+        warn!("Failed to install the interrupt handler: {err}");
+    }
+    engine.set_stop_flag(stop);
+    let mut connection = None;
+    let parsing_started = Instant::now();
+    let processed = prepare(&mut engine, &options).and_then(|()| {
+        if let Some(addr) = &options.listen {
+            let (stream, peer) = accept(addr)?;
+            engine.process_stream(&stream, &peer)?;
+            connection = Some(stream);
+            Ok(())
+        } else if options.merge {
+            engine.process_merged(&inputs)
+        } else {
+            engine.process_files(&inputs)
+        }
+    });
+    if processed.is_ok()
+        && let Some(fee) = options.fee
+    {
+        engine.apply_fee(fee);
+    }
+    if processed.is_ok()
+        && let Some(max_ratio) = options.max_hold_ratio
+    {
+        engine.check_hold_ratio(max_ratio);
+    }
+    let discrepancies = engine.discrepancies();
+    for discrepancy in &discrepancies {
+        error!("Invariant violated: {discrepancy}");
+    }
+    let held_drift = if options.check_invariants { engine.held_drift() } else { Vec::new() };
+    for client in &held_drift {
+        error!("Invariant violated: client {client} holds funds other than the sum held by its open disputes");
+    }
+    let parsing = parsing_started.elapsed();
+    if !options.quiet {
+        for line in engine.explanation() {
+            eprintln!("{line}");
+        }
+    }
+    if options.warn_empty && processed.is_ok() && engine.stats().records == 0 {
+        warn!("The input holds no transactions");
+    }
+    if options.summary && !options.quiet {
+        eprintln!("Summary: {}", engine.stats());
+    }
+
+    let mut partial_error = None;
+    let processed = match processed {
+        Err(err) if options.partial_on_error || matches!(err, Error::Interrupted(_)) => {
+            partial_error = Some(err);
+            Ok(())
+        }
+        processed => processed,
+    };
+    let records = engine.stats().records;
+    let failed_line = engine.failed_line();
+
+    processed
+        .and_then(|()| {
+            if let Some(path) = &options.dispute_detail {
+                write_dispute_detail(engine.accounts(), &options.output, File::create(path)?)?;
+            }
+            let serialization_started = Instant::now();
+            match connection {
+                Some(stream) => write_output(engine, &settings, &options, stream)?,
+                None => write_output(engine, &settings, &options, io::stdout().lock())?,
+            }
+            if options.timing && !options.quiet {
+                let timing = Timing { parsing, serialization: serialization_started.elapsed() };
+                eprintln!("Timing: {timing}");
+            }
+            Ok(())
         })
         .unwrap_or_else(|err| {
-            eprintln!("Error: {err}");
+            error!("{}", describe(&err, color));
             std::process::exit(1);
         });
+    if let Some(err @ Error::Interrupted(_)) = &partial_error {
+        warn!("{err}, accounts reflect the input read before the interrupt");
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+    if let Some(err) = partial_error {
+        match err.line().or(failed_line) {
+            Some(line) => error!("Stopped on line {line}, accounts reflect the input before it: {}", describe(&err, color)),
+            None => error!("Stopped at record {records}, accounts reflect the input before it: {}", describe(&err, color)),
+        }
+        std::process::exit(1);
+    }
+    if !discrepancies.is_empty() || !held_drift.is_empty() {
+        std::process::exit(1);
+    }
+    if options.fail_on_warn && logging::warnings() > 0 {
+        error!("Failing because {} warnings were logged", logging::warnings());
+        std::process::exit(1);
+    }
+}
+
+/// Prefixes the error with its severity with `--color`, colored when stderr is a terminal.
+fn describe(err: &Error, color: bool) -> String {
+    if !color {
+        err.to_string()
+    } else if err.is_system_error() {
+        format!("{}: {err}", "system error".if_supports_color(Stream::Stderr, |label| label.red()))
+    } else {
+        format!("{}: {err}", "user error".if_supports_color(Stream::Stderr, |label| label.yellow()))
+    }
+}
+
+/// Restores state from an earlier run and starts journaling before the inputs are processed.
+fn prepare(engine: &mut Engine, options: &Options) -> Result<(), Error> {
+    if let Some(client) = options.explain {
+        engine.set_explain(client);
+    }
+    if options.check_invariants {
+        engine.set_audit(Audit::default());
+    }
+    if let Some(path) = &options.seed_accounts {
+        engine.seed_accounts(read_seed_accounts(File::open(path)?)?, options.seed_mode)?;
+    }
+    if let Some(path) = &options.resume {
+        engine.replay_journal(path)?;
+    }
+    if let Some(path) = &options.journal {
+        engine.set_journal(Journal::open(path)?);
+    }
+    if let Some(path) = &options.dedupe_store {
+        engine.set_dedupe_store(DedupeStore::open(path)?);
+    }
+    if let Some(since_tx) = options.since_tx {
+        engine.set_since_tx(since_tx);
+    }
+    if let Some(max) = options.max_tx_per_client {
+        engine.set_max_tx_per_client(max, options.max_tx_action);
+    }
+    if let Some(every) = options.snapshot_every {
+        engine.set_snapshot_every(every, ".", options.output.clone());
+    }
+    Ok(())
+}
+
+/// Writes the accounts, printing a digest of the written bytes to stderr if asked for.
+fn write_output<W: io::Write>(engine: Engine, settings: &Settings, options: &Options, out: W) -> Result<(), Error> {
+    if !options.output_digest {
+        return write_accounts(engine, settings, &options.output, out);
+    }
+    let mut out = DigestWriter::new(out);
+    write_accounts(engine, settings, &options.output, &mut out)?;
+    if !options.quiet {
+        eprintln!("Output digest: {}", out.digest());
+    }
+    Ok(())
+}
+
+/// One row per account, or per account and currency with `Settings.multi_currency`.
+fn write_accounts<W: io::Write>(engine: Engine, settings: &Settings, options: &OutputOptions, out: W) -> Result<(), Error> {
+    if settings.multi_currency {
+        write_currency_accounts_to(engine.into_currency_accounts(), options, out)
+    } else {
+        write_accounts_to(engine.into_accounts(), options, out)
+    }
+}
+
+/// Waits for a single connection on `addr`, returning it with the peer address.
+fn accept(addr: &str) -> Result<(TcpStream, String), Error> {
+    let listener = TcpListener::bind(addr)?;
+    info!("Listening on {}", listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    Ok((stream, peer.to_string()))
 }
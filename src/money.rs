@@ -0,0 +1,37 @@
+use crate::Amount;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Operations `Account` needs from its amount type, so a backend with a wider range, such as an
+/// `i128` mantissa, can replace `Amount`. Arithmetic is checked, overflow yields `None`.
+pub trait Money: Copy + Ord + Default + Display + FromStr {
+    const ZERO: Self;
+    const MIN: Self;
+    const MAX: Self;
+
+    fn checked_add(self, other: Self) -> Option<Self>;
+
+    fn checked_sub(self, other: Self) -> Option<Self>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+}
+
+impl Money for Amount {
+    const ZERO: Self = Amount::ZERO;
+    const MIN: Self = Amount::MIN;
+    const MAX: Self = Amount::MAX;
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        Amount::checked_add(self, other)
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        Amount::checked_sub(self, other)
+    }
+
+    fn is_zero(&self) -> bool {
+        Amount::is_zero(self)
+    }
+}
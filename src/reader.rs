@@ -1,16 +1,21 @@
-use crate::account::{Account, AccountError};
+use crate::account::Account;
+use crate::engine::Engine;
 use crate::error::Error;
 use crate::prelude::*;
-use csv::{ByteRecord, ReaderBuilder, WriterBuilder};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
+use crate::settings::Settings;
+use csv::{ByteRecord, ReaderBuilder};
+use primitive_fixed_point_decimal::ParseError;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
 use std::str::from_utf8;
-use primitive_fixed_point_decimal::ConstScaleFpdec;
-use crate::Amount;
+use crate::{Amount, ClientId};
 
-#[derive(Debug, serde::Deserialize)]
-enum TransactionType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+pub enum TransactionType {
     #[serde(rename = "deposit")]
     Deposit,
     #[serde(rename = "withdrawal")]
@@ -21,119 +26,600 @@ enum TransactionType {
     Dispute,
     #[serde(rename = "chargeback")]
     Chargeback,
+    #[serde(rename = "freeze")]
+    Freeze,
+    #[serde(rename = "unfreeze")]
+    Unfreeze,
+    #[serde(rename = "close")]
+    Close,
+    #[serde(rename = "transfer")]
+    Transfer,
+    #[serde(rename = "adjust")]
+    Adjust,
 }
 
-#[derive(Debug, serde::Serialize)]
-pub struct AccountRecord {
-    client: u16,
-    available: String,
-    held: String,
-    total: String,
-    locked: bool,
+impl TransactionType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Freeze => "freeze",
+            TransactionType::Unfreeze => "unfreeze",
+            TransactionType::Close => "close",
+            TransactionType::Transfer => "transfer",
+            TransactionType::Adjust => "adjust",
+        }
+    }
+
+    /// Whether the transaction moves the amount given in its row.
+    pub fn carries_amount(self) -> bool {
+        matches!(self, TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Transfer)
+    }
+
+    /// Administrative operations, only accepted with `Settings.allow_admin_ops`.
+    pub fn is_admin(self) -> bool {
+        matches!(
+            self,
+            TransactionType::Freeze | TransactionType::Unfreeze | TransactionType::Close | TransactionType::Adjust
+        )
+    }
+
+    /// Whether the transaction refers to an earlier deposit or withdrawal by its id.
+    pub fn references_prior(self) -> bool {
+        matches!(self, TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback)
+    }
 }
 
-impl From<Account> for AccountRecord {
-    fn from(account: Account) -> Self {
-        AccountRecord {
-            client: account.client,
-            available: account.funds_available.to_string(),
-            held: account.funds_held.to_string(),
-            total: (account.funds_held + account.funds_available).to_string(),
-            locked: account.locked
-        }
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    pub kind: TransactionType,
+    pub client: ClientId,
+    pub tx: u64,
+    pub amount: Option<Amount>,
+    /// Receiving client of a transfer
+    pub dest_client: Option<ClientId>,
+    /// Currency of the amounts, only read with `Settings.multi_currency`
+    pub currency: Option<String>,
+    pub line: u64,
+}
+
+/// Reads `Transaction`s from CSV input.
+pub struct TransactionReader<'a, R> {
+    reader: csv::Reader<R>,
+    record: ByteRecord,
+    parser: RecordParser<'a>,
+}
+
+/// Turns the fields of a record into a `Transaction`, shared by the CSV and fixed-width readers.
+struct RecordParser<'a> {
+    settings: &'a Settings,
+    columns: ColumnIndices,
+    type_aliases: HashMap<Vec<u8>, TransactionType>,
+}
+
+/// Positions of the input columns within a record; optional columns missing from the input
+/// point past the end of every record.
+struct ColumnIndices {
+    kind: usize,
+    client: usize,
+    tx: usize,
+    amount: usize,
+    dest_client: usize,
+    currency: usize,
+}
+
+impl ColumnIndices {
+    /// Positions named in `Settings.input_columns`, otherwise `type,client,tx,amount`. The
+    /// `dest_client` and `currency` columns are only found when the header names them, so
+    /// further columns of headerless input are ignored.
+    fn new(settings: &Settings, headers: Option<&ByteRecord>) -> Result<Self> {
+        let Some(names) = &settings.input_columns else {
+            let header = |column: &[u8]| headers.and_then(|headers| headers.iter().position(|name| trim_ascii(name) == column));
+            let currency = header(b"currency").unwrap_or(usize::MAX);
+            let dest_client = header(b"dest_client").unwrap_or(usize::MAX);
+            return Ok(ColumnIndices { kind: 0, client: 1, tx: 2, amount: 3, dest_client, currency });
+        };
+        let position = |column: &str| names.iter().position(|name| name == column);
+        let required = |column: &'static str| position(column).ok_or(Error::MissingInputColumn(column));
+        Ok(ColumnIndices {
+            kind: required("type")?,
+            client: required("client")?,
+            tx: required("tx")?,
+            amount: position("amount").unwrap_or(usize::MAX),
+            dest_client: position("dest_client").unwrap_or(usize::MAX),
+            currency: position("currency").unwrap_or(usize::MAX),
+        })
     }
 }
 
+impl<'a> TransactionReader<'a, BufReader<File>> {
+    pub fn from_path(path: &str, settings: &'a Settings) -> Result<Self> {
+        let buffered_reader = BufReader::with_capacity(settings.buffer_capacity(), File::open(path)?);
+        Self::new(buffered_reader, settings)
+    }
+}
 
-pub fn write_accounts(accounts: HashMap<u16, Account>) -> Result<String> {
-    let mut writer = WriterBuilder::new().from_writer(vec![]);
-    for (_client_id, account) in accounts {
-        writer.serialize(AccountRecord::from(account))?;
+impl<'a, R: Read> TransactionReader<'a, R> {
+    pub fn new(input: R, settings: &'a Settings) -> Result<Self> {
+        let delimiter = u8::try_from(settings.delimiter)
+            .ok()
+            .filter(u8::is_ascii)
+            .ok_or(Error::InvalidDelimiter(settings.delimiter))?;
+        let comment = settings
+            .comment_char
+            .map(|comment| {
+                u8::try_from(comment)
+                    .ok()
+                    .filter(u8::is_ascii)
+                    .ok_or(Error::InvalidCommentChar(comment))
+            })
+            .transpose()?;
+        let mut reader = ReaderBuilder::new()
+            .has_headers(settings.has_headers)
+            .flexible(true)
+            .delimiter(delimiter)
+            .comment(comment)
+            .trim(settings.trim.into())
+            .buffer_capacity(settings.buffer_capacity()) // if your csv crate version supports it
+            .from_reader(input);
+        let headers = if settings.has_headers {
+            let headers = reader.byte_headers()?;
+            validate_headers(headers)?;
+            if settings.enforce_header {
+                enforce_header(headers, settings)?;
+            }
+            Some(headers)
+        } else {
+            None
+        };
+        let columns = ColumnIndices::new(settings, headers)?;
+        Ok(TransactionReader {
+            reader,
+            record: ByteRecord::new(),
+            parser: RecordParser::new(settings, columns)?,
+        })
+    }
+
+    /// Row errors come as `Error::Located` with the position the reader reached: the byte
+    /// offset just past the record, and the line numbered like the errors themselves.
+    fn read_transaction(&mut self) -> Result<Option<Transaction>> {
+        if !self.reader.read_byte_record(&mut self.record)? {
+            return Ok(None);
+        }
+        let position = self.reader.position();
+        self.parser.parse(&self.record, position.line()).map(Some).map_err(|err| {
+            if err.is_row_error() {
+                Error::Located { source: Box::new(err), line: position.line(), byte: position.byte() }
+            } else {
+                err
+            }
+        })
     }
-    let vec = writer.into_inner().map_err(|err| Error::from(err.into_error()))?;
-    String::from_utf8(vec).map_err(|err| err.utf8_error().into())
 }
 
-pub fn parse_csv(file: &str, buffer_capacity: usize) -> Result<HashMap<u16, Account>> {
-    let file = File::open(file)?;
-    let buffered_reader = BufReader::with_capacity(buffer_capacity, file);
-    let mut reader = ReaderBuilder::new()
-        .has_headers(true)                // your sample has a header row
-        .flexible(true)
-        .trim(csv::Trim::All)// faster when row length is fixed
-        .buffer_capacity(buffer_capacity) // if your csv crate version supports it
-        .from_reader(buffered_reader);
+impl<R: Read> Iterator for TransactionReader<'_, R> {
+    type Item = Result<Transaction>;
 
-    let mut accounts: HashMap<u16, Account> = HashMap::new();
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_transaction().transpose()
+    }
+}
 
-    let mut record = ByteRecord::new();
-    while reader.read_byte_record(&mut record)? {
-        let line_number = reader.position().line();
+impl<'a> RecordParser<'a> {
+    fn new(settings: &'a Settings, columns: ColumnIndices) -> Result<Self> {
+        let type_aliases = settings
+            .type_aliases
+            .iter()
+            .map(|(alias, name)| {
+                parse_transaction_type(name.as_bytes(), 0)
+                    .map(|kind| (alias.as_bytes().to_vec(), kind))
+                    .map_err(|_| Error::InvalidTypeAlias(alias.clone()))
+            })
+            .collect::<Result<_>>()?;
+        if let Some(symbol) = settings.strip_currency_symbols.iter().find(|symbol| {
+            symbol.is_empty()
+                || symbol.contains(|c: char| c.is_ascii_digit() || matches!(c, '+' | '-' | '.') || c == settings.decimal_separator)
+        }) {
+            return Err(Error::InvalidCurrencySymbol(symbol.clone()));
+        }
+        Ok(RecordParser { settings, columns, type_aliases })
+    }
+
+    fn parse(&self, record: &ByteRecord, line_number: u64) -> Result<Transaction> {
 
-        let transaction_type = record.get(0)
+        let transaction_type = record.get(self.columns.kind)
             .ok_or(Error::MissingTransactionType(line_number))
-            .and_then(|raw| parse_transaction_type(raw, line_number))?;
-        let client = record.get(1)
+            .and_then(|raw| self.transaction_type(raw, line_number))?;
+        let client = record.get(self.columns.client)
             .ok_or(Error::MissingClient(line_number))
-            .and_then(|client| lexical_core::parse::<u16>(client).map_err(Error::from))?;
-        let transaction_id = record.get(2)
+            .and_then(|client| lexical_core::parse::<ClientId>(client).map_err(|_| Error::InvalidClient(line_number)))?;
+        if let Some(max_client_id) = self.settings.max_client_id
+            && client > max_client_id
+        {
+            return Err(Error::ClientOutOfRange(client.into(), line_number));
+        }
+        let transaction_id = record.get(self.columns.tx)
             .ok_or(Error::MissingTransactionId(line_number))
             .and_then(|transaction_id| lexical_core::parse::<u64>(transaction_id).map_err(Error::from))?;
+        if transaction_id == 0 && self.settings.reject_zero_tx_id {
+            return Err(Error::InvalidTransactionId(line_number));
+        }
 
-        let amount_row: Option<Amount> = record.get(3)
-            .map(|raw| parse_scaled_value(raw, line_number))
+        let amount_row: Option<Amount> = record.get(self.columns.amount)
+            .map(|raw| parse_scaled_value(raw, line_number, self.settings))
             .transpose()?
             .flatten();
+        let is_transfer = transaction_type == TransactionType::Transfer && self.settings.allow_transfers;
+        let dest_client = match record.get(self.columns.dest_client).map(trim_ascii) {
+            Some(raw) if is_transfer && !raw.is_empty() => {
+                Some(lexical_core::parse::<ClientId>(raw).map_err(|_| Error::InvalidClient(line_number))?)
+            }
+            _ => None,
+        };
+        if let (Some(dest_client), Some(max_client_id)) = (dest_client, self.settings.max_client_id)
+            && dest_client > max_client_id
+        {
+            return Err(Error::ClientOutOfRange(dest_client.into(), line_number));
+        }
+        let currency = if self.settings.multi_currency {
+            match record.get(self.columns.currency).map(trim_ascii) {
+                Some(raw) if !raw.is_empty() => Some(String::from_utf8_lossy(raw).into_owned()),
+                _ => return Err(Error::MissingCurrency(line_number)),
+            }
+        } else {
+            None
+        };
 
-        let account = accounts
-            .entry(client)
-            .or_insert_with_key(|&client| Account::new(client));
+        Ok(Transaction {
+            kind: transaction_type,
+            client,
+            tx: transaction_id,
+            amount: amount_row,
+            dest_client,
+            currency,
+            line: line_number,
+        })
+    }
 
-        match transaction_type {
-            TransactionType::Deposit => {
-                let amount = amount_row.ok_or(Error::MissingAmount(line_number))?;
-                account.deposit(transaction_id, amount);
-            }
-            TransactionType::Withdrawal => {
-                let amount = amount_row.ok_or(Error::MissingAmount(line_number))?;
-                account.withdraw(transaction_id, amount);
+    /// Looks the type up among the configured aliases before the standard names.
+    fn transaction_type(&self, raw: &[u8], line_number: u64) -> Result<TransactionType> {
+        if let Some(&kind) = self.type_aliases.get(trim_ascii(raw)) {
+            return Ok(kind);
+        }
+        parse_transaction_type(raw, line_number)
+    }
+}
+
+/// Reads `Transaction`s from fixed-width records, one per line, with the columns at the byte
+/// spans of `Settings.fixed_width`. There is no header row; blank lines and lines starting
+/// with the comment character are skipped.
+pub struct FixedWidthReader<'a, R> {
+    input: R,
+    line: Vec<u8>,
+    line_number: u64,
+    spans: [Option<(usize, usize)>; 6],
+    record: ByteRecord,
+    parser: RecordParser<'a>,
+}
+
+impl<'a> FixedWidthReader<'a, BufReader<File>> {
+    pub fn from_path(path: &str, settings: &'a Settings) -> Result<Self> {
+        let buffered_reader = BufReader::with_capacity(settings.buffer_capacity(), File::open(path)?);
+        Self::new(buffered_reader, settings)
+    }
+}
+
+impl<'a, R: BufRead> FixedWidthReader<'a, R> {
+    pub fn new(input: R, settings: &'a Settings) -> Result<Self> {
+        let spans = settings.fixed_width.as_ref().ok_or(Error::MissingFixedWidth)?;
+        // Fields are laid out in a record in the default column order
+        let columns = ColumnIndices { kind: 0, client: 1, tx: 2, amount: 3, dest_client: 4, currency: 5 };
+        Ok(FixedWidthReader {
+            input,
+            line: Vec::new(),
+            line_number: 0,
+            spans: [Some(spans.kind), Some(spans.client), Some(spans.tx), spans.amount, spans.dest_client, spans.currency],
+            record: ByteRecord::new(),
+            parser: RecordParser::new(settings, columns)?,
+        })
+    }
+
+    fn read_transaction(&mut self) -> Result<Option<Transaction>> {
+        loop {
+            self.line.clear();
+            if self.input.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(None);
             }
-            TransactionType::Dispute => {
-                account.dispute(transaction_id).map_err(|err| match err {
-                    AccountError::NoTransaction(tx_id) => Error::NoTransaction(tx_id, line_number),
-                    AccountError::NoDispute(tx_id) => Error::NoDispute(tx_id, line_number),
-                })?;
+            self.line_number += 1;
+            let line = self.line.strip_suffix(b"\n").unwrap_or(&self.line);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let comment = self
+                .parser
+                .settings
+                .comment_char
+                .is_some_and(|comment| line.first().is_some_and(|&first| char::from(first) == comment));
+            if trim_ascii(line).is_empty() || comment {
+                continue;
             }
-            TransactionType::Resolve => {
-                account.resolve(transaction_id).map_err(|err| match err {
-                    AccountError::NoTransaction(tx_id) => Error::NoTransaction(tx_id, line_number),
-                    AccountError::NoDispute(tx_id) => Error::NoDispute(tx_id, line_number),
-                })?;
+            self.record.clear();
+            for span in self.spans {
+                self.record.push_field(span.map_or(&[][..], |span| field_at(line, span)));
             }
-            TransactionType::Chargeback => {
-                account.chargeback(transaction_id).map_err(|err| match err {
-                    AccountError::NoTransaction(tx_id) => Error::NoTransaction(tx_id, line_number),
-                    AccountError::NoDispute(tx_id) => Error::NoDispute(tx_id, line_number),
-                })?;
+            return self.parser.parse(&self.record, self.line_number).map(Some);
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for FixedWidthReader<'_, R> {
+    type Item = Result<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_transaction().transpose()
+    }
+}
+
+/// The trimmed bytes of `line` within `[start, end)`, cut short where the line ends.
+fn field_at(line: &[u8], (start, end): (usize, usize)) -> &[u8] {
+    let end = end.min(line.len());
+    trim_ascii(&line[start.min(end)..end])
+}
+
+/// Merges several transaction streams, each ordered by transaction id, into one
+/// stream in ascending transaction id order.
+///
+/// For equal ids deposits and withdrawals come before the disputes referencing
+/// them; remaining ties keep the order of the sources.
+pub struct MergeByTransactionId<I> {
+    sources: Vec<I>,
+    heads: Vec<Option<Transaction>>,
+    queue: BinaryHeap<Reverse<(u64, bool, usize)>>,
+    refill: Vec<usize>,
+}
+
+impl<I: Iterator<Item = Result<Transaction>>> MergeByTransactionId<I> {
+    pub fn new(sources: Vec<I>) -> Self {
+        let heads = sources.iter().map(|_| None).collect();
+        let refill = (0..sources.len()).rev().collect();
+        MergeByTransactionId {
+            sources,
+            heads,
+            queue: BinaryHeap::new(),
+            refill,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<Transaction>>> Iterator for MergeByTransactionId<I> {
+    type Item = Result<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(index) = self.refill.pop() {
+            match self.sources[index].next() {
+                Some(Ok(transaction)) => {
+                    self.queue
+                        .push(Reverse((transaction.tx, transaction.kind.references_prior(), index)));
+                    self.heads[index] = Some(transaction);
+                }
+                Some(Err(err)) => {
+                    // Keep reading the source, its later rows still apply when invalid rows are skipped
+                    self.refill.push(index);
+                    return Some(Err(err));
+                }
+                None => {}
             }
         }
+        let Reverse((_, _, index)) = self.queue.pop()?;
+        self.refill.push(index);
+        self.heads[index].take().map(Ok)
     }
+}
 
+/// Reads opening balances from a CSV with a `client,available,held,locked` header, in any
+/// column order. `held` and `locked` may be left out and default to zero and unlocked.
+pub fn read_seed_accounts<R: Read>(input: R) -> Result<Vec<Account>> {
+    let mut reader = ReaderBuilder::new().trim(csv::Trim::All).from_reader(input);
+    let headers = reader.byte_headers()?;
+    let position = |column: &[u8]| headers.iter().position(|name| name == column);
+    let client = position(b"client").ok_or(Error::MissingInputColumn("client"))?;
+    let available = position(b"available").ok_or(Error::MissingInputColumn("available"))?;
+    let (held, locked) = (position(b"held"), position(b"locked"));
+    let mut accounts = Vec::new();
+    for record in reader.byte_records() {
+        let record = record?;
+        let invalid = || Error::InvalidRecord(String::from_utf8_lossy(record.as_slice()).into_owned());
+        let field = |index: usize| record.get(index).ok_or_else(invalid);
+        let mut account = Account::new(lexical_core::parse(field(client)?).map_err(|_| invalid())?);
+        account.funds_available = parse_amount(field(available)?).map_err(|_| invalid())?;
+        if let Some(held) = held {
+            account.funds_held = parse_amount(field(held)?).map_err(|_| invalid())?;
+        }
+        if let Some(locked) = locked {
+            account.locked = match field(locked)? {
+                b"true" => true,
+                b"false" => false,
+                _ => return Err(invalid()),
+            };
+        }
+        accounts.push(account);
+    }
     Ok(accounts)
 }
 
+/// Processes a fixed-width file laid out by `Settings.fixed_width`.
+pub fn parse_fixed_width(path: &str, settings: &Settings) -> Result<HashMap<ClientId, Account>> {
+    let mut engine = Engine::new(settings);
+    engine.process(FixedWidthReader::from_path(path, settings)?)?;
+    Ok(engine.into_accounts())
+}
+
+/// The accounts built from the inputs along with how many of their rows were applied.
+#[derive(Default)]
+pub struct ProcessResult {
+    pub accounts: HashMap<ClientId, Account>,
+    /// Rows applied to an account
+    pub processed: u64,
+    /// Rows read but not applied: ignored, already applied by an earlier run, or invalid
+    pub skipped: u64,
+    /// Invalid rows among the skipped ones, only counted with `Settings.skip_invalid_rows`
+    pub errors: u64,
+}
+
+impl From<Engine<'_>> for ProcessResult {
+    fn from(engine: Engine<'_>) -> Self {
+        let stats = engine.stats();
+        let skipped = stats.skipped + stats.replayed + stats.errors;
+        ProcessResult {
+            processed: stats.records - skipped,
+            skipped,
+            errors: stats.errors,
+            accounts: engine.into_accounts(),
+        }
+    }
+}
+
+pub fn parse_csv(path: &str, settings: &Settings) -> Result<HashMap<ClientId, Account>> {
+    parse_csv_files(&[path], settings)
+}
+
+/// Like `parse_csv`, also counting the rows processed and skipped.
+pub fn process_csv(path: &str, settings: &Settings) -> Result<ProcessResult> {
+    process_csv_files(&[path], settings)
+}
+
+/// Like `parse_csv`, creating the account of every new client with `factory`.
+pub fn parse_csv_with(
+    path: &str,
+    settings: &Settings,
+    factory: impl Fn(ClientId) -> Account,
+) -> Result<HashMap<ClientId, Account>> {
+    let mut engine = Engine::new(settings);
+    engine.set_account_factory(factory);
+    engine.process_files(&[path])?;
+    Ok(engine.into_accounts())
+}
+
+/// Processes the files one after another into a shared set of accounts.
+pub fn parse_csv_files(paths: &[&str], settings: &Settings) -> Result<HashMap<ClientId, Account>> {
+    process_csv_files(paths, settings).map(|result| result.accounts)
+}
+
+/// Like `parse_csv_files`, also counting the rows processed and skipped.
+pub fn process_csv_files(paths: &[&str], settings: &Settings) -> Result<ProcessResult> {
+    let mut engine = Engine::new(settings);
+    engine.process_files(paths)?;
+    Ok(engine.into())
+}
+
+/// Processes the files as one stream merged in ascending transaction id order.
+pub fn parse_csv_merged(paths: &[&str], settings: &Settings) -> Result<HashMap<ClientId, Account>> {
+    let mut engine = Engine::new(settings);
+    engine.process_merged(paths)?;
+    Ok(engine.into_accounts())
+}
+
+/// Replaces every directory among `paths` with the `.csv` files it contains, sorted by
+/// file name. Other files in the directory are skipped; plain file paths are kept as given.
+pub fn expand_inputs<S: AsRef<str>>(paths: &[S]) -> Result<Vec<String>> {
+    let mut inputs = Vec::with_capacity(paths.len());
+    for path in paths {
+        let path = path.as_ref();
+        if !Path::new(path).is_dir() {
+            inputs.push(path.to_string());
+            continue;
+        }
+        let mut files = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let file = entry?.path();
+            let is_csv = file
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+            if is_csv && file.is_file() {
+                files.push(file);
+            }
+        }
+        files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        inputs.extend(files.iter().map(|file| file.to_string_lossy().into_owned()));
+    }
+    Ok(inputs)
+}
+
+/// Processes an in-memory CSV document. Any input yields `Ok` or `Err`, never a panic,
+/// which makes this the entry point for fuzzing.
+pub fn parse_bytes(input: &[u8], settings: &Settings) -> Result<HashMap<ClientId, Account>> {
+    let mut engine = Engine::new(settings);
+    engine.process(TransactionReader::new(input, settings)?)?;
+    Ok(engine.into_accounts())
+}
+
+/// Reads every row of the input without applying it, collecting the error of each invalid row
+/// as `Error::Located`, so tools can point at it in the source. Fails on the first error that
+/// isn't tied to a row.
+pub fn validate<R: Read>(input: R, settings: &Settings) -> Result<Vec<Error>> {
+    let mut reader = TransactionReader::new(input, settings)?;
+    let mut errors = Vec::new();
+    loop {
+        match reader.read_transaction() {
+            Ok(Some(_)) => {}
+            Ok(None) => return Ok(errors),
+            Err(err) if err.is_row_error() => errors.push(err),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Rejects header rows naming the same column twice, which would make column lookup ambiguous.
+fn validate_headers(headers: &ByteRecord) -> Result<()> {
+    for (index, name) in headers.iter().enumerate() {
+        let name = trim_ascii(name);
+        if headers.iter().take(index).any(|previous| trim_ascii(previous) == name) {
+            return Err(Error::DuplicateHeader(String::from_utf8_lossy(name).into_owned()));
+        }
+    }
+    Ok(())
+}
+
+/// Rejects a header row that isn't exactly the configured or default input columns.
+fn enforce_header(headers: &ByteRecord, settings: &Settings) -> Result<()> {
+    const DEFAULT_COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+    let expected: Vec<&str> = match &settings.input_columns {
+        Some(names) => names.iter().map(String::as_str).collect(),
+        None => DEFAULT_COLUMNS.to_vec(),
+    };
+    let matches = headers.len() == expected.len()
+        && headers.iter().zip(&expected).all(|(name, expected)| trim_ascii(name) == expected.as_bytes());
+    if !matches {
+        let found: Vec<_> = headers.iter().map(String::from_utf8_lossy).collect();
+        return Err(Error::UnexpectedHeader(found.join(",")));
+    }
+    Ok(())
+}
+
+/// Parses a transaction type name, ignoring surrounding ASCII whitespace. The first byte picks
+/// the candidate name, so most rows need a single comparison; the names sharing a first byte,
+/// `deposit`/`dispute` and `chargeback`/`close`, fall back to comparing both in full.
 #[inline]
-fn parse_transaction_type(raw: &[u8], line_number: u64) -> Result<TransactionType> {
+pub fn parse_transaction_type(raw: &[u8], line_number: u64) -> Result<TransactionType> {
+    use TransactionType::*;
     // Avoid allocations: compare against byte literals after trimming.
     let b = trim_ascii(raw);
-    match b {
-        b"deposit"     => Ok(TransactionType::Deposit),
-        b"withdrawal"  => Ok(TransactionType::Withdrawal),
-        b"dispute"     => Ok(TransactionType::Dispute),
-        b"resolve"     => Ok(TransactionType::Resolve),
-        b"chargeback"   => Ok(TransactionType::Chargeback),
-        _              => Err(Error::UnknownTransactionType(line_number)),
-    }
+    let kind = match b.first() {
+        None => return Err(Error::MissingTransactionType(line_number)),
+        Some(b'd') if b == b"deposit" => Deposit,
+        Some(b'd') if b == b"dispute" => Dispute,
+        Some(b'w') if b == b"withdrawal" => Withdrawal,
+        Some(b'r') if b == b"resolve" => Resolve,
+        Some(b'c') if b == b"chargeback" => Chargeback,
+        Some(b'c') if b == b"close" => Close,
+        Some(b'f') if b == b"freeze" => Freeze,
+        Some(b'u') if b == b"unfreeze" => Unfreeze,
+        Some(b't') if b == b"transfer" => Transfer,
+        Some(b'a') if b == b"adjust" => Adjust,
+        Some(_) => return Err(Error::UnknownTransactionType(line_number)),
+    };
+    Ok(kind)
 }
 
 #[inline]
@@ -145,27 +631,208 @@ fn trim_ascii(bytes: &[u8]) -> &[u8] {
     &bytes[start..end]
 }
 
+/// Amounts are never rounded: more than four fractional digits, even trailing zeros, fail to
+/// parse rather than being reduced to the scale of `Amount`.
 #[inline]
-fn parse_scaled_value(byte_array: &[u8], line_number: u64) -> Result<Option<Amount>> {
-    let byte_array = trim_ascii(byte_array);
+fn parse_scaled_value(byte_array: &[u8], line_number: u64, settings: &Settings) -> Result<Option<Amount>> {
+    let byte_array = if settings.trim.trims_fields() { trim_ascii(byte_array) } else { byte_array };
     if byte_array.is_empty() { return Ok(None); }
-    if byte_array[0] == b'-' { return Err(Error::NegativeAmount(line_number)); }
-    let scaled_value: ConstScaleFpdec<i64, 4> = from_utf8(byte_array)?
-        .trim()
-        .parse()?;
+    let byte_array = strip_currency_symbol(byte_array, settings);
+    if byte_array.is_empty() { return Err(Error::MissingAmount(line_number)); }
+    if byte_array.len() > settings.max_amount_len { return Err(Error::AmountTooLong(line_number)); }
+    if byte_array.starts_with(b"-") { return Err(Error::NegativeAmount(line_number)); }
+    if settings.strict_numeric && !is_canonical(byte_array) {
+        return Err(Error::NonCanonicalAmount(line_number));
+    }
+    if let Some(amount) = parse_plain_amount(byte_array) { return Ok(Some(amount)); }
+    let raw = from_utf8(byte_array)?;
+    let raw = if settings.decimal_separator == '.' {
+        Cow::Borrowed(raw)
+    } else {
+        Cow::Owned(raw.replace(settings.decimal_separator, "."))
+    };
+    let raw = if settings.lenient_decimal_format { complete_decimal(raw) } else { raw };
+    let scaled_value: Amount = if settings.accept_scientific_notation {
+        expand_scientific(&raw)?.parse()?
+    } else {
+        raw.parse()?
+    };
     Ok(Some(scaled_value))
 }
 
+/// Removes the first of `settings.strip_currency_symbols` the amount starts with, along with
+/// whitespace after it when fields are trimmed, e.g. `€ 100,50` to `100,50`. This runs before
+/// the decimal separator is rewritten, which the symbols are checked not to contain.
+fn strip_currency_symbol<'b>(byte_array: &'b [u8], settings: &Settings) -> &'b [u8] {
+    let Some(rest) = settings
+        .strip_currency_symbols
+        .iter()
+        .find_map(|symbol| byte_array.strip_prefix(symbol.as_bytes()))
+    else {
+        return byte_array;
+    };
+    if settings.trim.trims_fields() { trim_ascii(rest) } else { rest }
+}
+
+/// Parses an amount in the default format, reading plain amounts such as `123.45` straight
+/// from the bytes and handing anything else to `Amount::from_str`.
+pub fn parse_amount(raw: &[u8]) -> Result<Amount> {
+    match parse_plain_amount(raw) {
+        Some(amount) => Ok(amount),
+        None => Ok(from_utf8(raw)?.parse()?),
+    }
+}
+
+/// Accumulates the mantissa of `digits[.digits]` with at most four fraction digits, `None` for
+/// any other shape or on overflow so the caller falls back to the full parser.
+fn parse_plain_amount(raw: &[u8]) -> Option<Amount> {
+    let (integer, fraction) = match raw.iter().position(|&byte| byte == b'.') {
+        Some(point) => (&raw[..point], &raw[point + 1..]),
+        None => (raw, &raw[raw.len()..]),
+    };
+    if integer.is_empty() || fraction.len() > 4 || (fraction.is_empty() && integer.len() < raw.len()) {
+        return None;
+    }
+    let mut mantissa: i64 = 0;
+    for &byte in integer.iter().chain(fraction) {
+        if !byte.is_ascii_digit() {
+            return None;
+        }
+        mantissa = mantissa.checked_mul(10)?.checked_add(i64::from(byte - b'0'))?;
+    }
+    for _ in fraction.len()..4 {
+        mantissa = mantissa.checked_mul(10)?;
+    }
+    Some(Amount::from_mantissa(mantissa))
+}
+
+/// Whether the amount has no leading `+`, no leading zeros before its integer digits and no
+/// `+` signed exponent. Anything else about its shape is left to the parser.
+fn is_canonical(raw: &[u8]) -> bool {
+    let integer_digits = raw.iter().take_while(|byte| byte.is_ascii_digit()).count();
+    raw[0] != b'+'
+        && !(integer_digits > 1 && raw[0] == b'0')
+        && !raw.windows(2).any(|pair| matches!(pair, [b'e' | b'E', b'+']))
+}
+
+/// Supplies the digits omitted in `.5` and `5.`, leaving a lone `.` to fail parsing.
+fn complete_decimal(raw: Cow<'_, str>) -> Cow<'_, str> {
+    if raw.len() < 2 {
+        raw
+    } else if raw.starts_with('.') {
+        Cow::Owned(format!("0{raw}"))
+    } else if let Some(integer) = raw.strip_suffix('.') {
+        Cow::Owned(integer.to_string())
+    } else {
+        raw
+    }
+}
+
+/// Rewrites `1.5e2` as `150` by moving the decimal point, so the result is parsed exactly
+/// instead of going through `f64`. Values without an exponent are returned unchanged.
+fn expand_scientific(raw: &str) -> std::result::Result<Cow<'_, str>, ParseError> {
+    let Some((mantissa, exponent)) = raw.split_once(['e', 'E']) else {
+        return Ok(Cow::Borrowed(raw));
+    };
+    let exponent = exponent.parse::<i32>().map_err(|_| ParseError::Invalid)?;
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let integer = integer.strip_prefix('+').unwrap_or(integer);
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    if !integer.bytes().chain(fraction.bytes()).all(|byte| byte.is_ascii_digit()) {
+        return Err(ParseError::Invalid);
+    }
+    let digits = format!("{integer}{fraction}");
+    let significant = digits.trim_start_matches('0');
+    if significant.is_empty() {
+        return Ok(Cow::Borrowed("0"));
+    }
+    // Position of the decimal point within the significant digits
+    let point = integer.len() as i64 - (digits.len() - significant.len()) as i64 + i64::from(exponent);
+    // Beyond these bounds the value can't fit an Amount anyway, so don't build huge strings
+    if point > 32 {
+        return Err(ParseError::Overflow);
+    }
+    if point < -32 {
+        return Err(ParseError::Precision);
+    }
+    let expanded = if point <= 0 {
+        format!("0.{}{significant}", "0".repeat(point.unsigned_abs() as usize))
+    } else if point as usize >= significant.len() {
+        format!("{significant}{}", "0".repeat(point as usize - significant.len()))
+    } else {
+        let (whole, decimals) = significant.split_at(point as usize);
+        format!("{whole}.{decimals}")
+    };
+    Ok(Cow::Owned(expanded))
+}
+
 // TODO tests for dispute behavior and states
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::amount;
+    use crate::settings::{BufferSettings, FixedWidth, TrimMode};
+    use crate::writer::{write_accounts, write_currency_accounts_to, OutputOptions};
+    use proptest::prelude::*;
+    use std::sync::{Mutex, OnceLock};
+
+    fn test_settings() -> Settings {
+        Settings {
+            buffer: BufferSettings { capacity: 8192 }, // Small buffer for testing
+            ..Default::default()
+        }
+    }
+
+    /// Captures log output so tests can assert on emitted messages.
+    struct TestLogger {
+        records: Mutex<Vec<(log::Level, String)>>,
+    }
+
+    impl log::Log for TestLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.records
+                .lock()
+                .expect("Test logger poisoned")
+                .push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn test_logger() -> &'static TestLogger {
+        static LOGGER: OnceLock<TestLogger> = OnceLock::new();
+        let logger = LOGGER.get_or_init(|| TestLogger { records: Mutex::new(Vec::new()) });
+        if log::set_logger(logger).is_ok() {
+            log::set_max_level(log::LevelFilter::Trace);
+        }
+        logger
+    }
+
+    fn logged(level: log::Level, needle: &str) -> bool {
+        test_logger()
+            .records
+            .lock()
+            .expect("Test logger poisoned")
+            .iter()
+            .any(|(record_level, message)| *record_level == level && message.contains(needle))
+    }
+
+    fn write_fixture(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("kraken_{}_{name}", std::process::id()));
+        std::fs::write(&path, contents).expect("Failed to write fixture");
+        path.to_string_lossy().into_owned()
+    }
 
     #[test]
     fn test_process_csv_basic_transactions() {
-        let buffer_capacity = 8192; // Small buffer for testing
-        let result = parse_csv("tests/fixtures/test_transactions.csv", buffer_capacity);
+        let result = parse_csv("tests/fixtures/test_transactions.csv", &test_settings());
 
         assert!(result.is_ok(), "Failed to process CSV: {:?}", result.err());
         let accounts = result.unwrap();
@@ -179,7 +846,7 @@ mod tests {
         // Client 1: deposit 100.0, deposit 50.25, withdrawal 25.0 = 125.25
         // After dispute and resolve of tx 3 (50.25), funds should be back to available
         assert_eq!(account1.funds_available.to_string(), "125.25");
-        assert_eq!(account1.funds_held.to_string(), "0");
+        assert!(account1.funds_held.is_zero());
         assert!(!account1.locked, "Client 1 should not be locked");
 
         // Check client 2
@@ -189,18 +856,292 @@ mod tests {
         // After dispute and chargeback of tx 2 (200.5), funds_held reduced by 200.5
         // Available: 150.5 - 200.5 (disputed) = -50.0
         // After chargeback, held is reduced to 0, and account is locked
-        assert_eq!(account2.funds_held.to_string(), "0");
+        assert!(account2.funds_held.is_zero());
         assert!(account2.locked, "Client 2 should be locked after chargeback");
     }
 
+    #[test]
+    fn test_process_csv_counts_rows() {
+        let settings = Settings {
+            skip_invalid_rows: true,
+            ..test_settings()
+        };
+
+        let result = process_csv("tests/fixtures/broken_midway.csv", &settings).expect("Failed to process CSV");
+
+        assert_eq!(result.processed, 4);
+        assert_eq!(result.skipped, 1);
+        assert_eq!(result.errors, 1);
+        assert_eq!(result.processed + result.skipped, 5);
+        assert_eq!(result.accounts.len(), 2);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_process_csv_records_metrics() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let path = write_fixture("metrics.csv", "type,client,tx,amount\ndeposit,1,1,10.0\ndeposit,2,2,5.0\nbogus,1,3,1.0\ndispute,2,2,\nchargeback,2,2,\n");
+        let settings = Settings {
+            skip_invalid_rows: true,
+            ..test_settings()
+        };
+
+        metrics::with_local_recorder(&recorder, || parse_csv(&path, &settings)).expect("Failed to process CSV");
+
+        let metrics: HashMap<(String, Vec<String>), DebugValue> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let key = key.key();
+                let labels = key.labels().map(|label| format!("{}={}", label.key(), label.value())).collect();
+                ((key.name().to_string(), labels), value)
+            })
+            .collect();
+        let metric = |name: &str, labels: &[&str]| {
+            metrics.get(&(name.to_string(), labels.iter().map(|label| label.to_string()).collect()))
+        };
+        assert_eq!(metric("transactions_total", &["type=deposit"]), Some(&DebugValue::Counter(2)));
+        assert_eq!(metric("transactions_total", &["type=dispute"]), Some(&DebugValue::Counter(1)));
+        assert_eq!(metric("transactions_total", &["type=chargeback"]), Some(&DebugValue::Counter(1)));
+        assert_eq!(metric("parse_errors_total", &[]), Some(&DebugValue::Counter(1)));
+        assert_eq!(metric("accounts_locked", &[]), Some(&DebugValue::Gauge(1.0.into())));
+    }
+
+    #[test]
+    fn test_parse_bytes_without_headers() {
+        let settings = Settings {
+            has_headers: false,
+            ..test_settings()
+        };
+
+        let accounts = parse_bytes(b"deposit,1,1,10.0\ndeposit,2,2,5.0\n", &settings).expect("Failed to parse input");
+
+        assert_eq!(accounts.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_bytes_with_input_columns() {
+        let settings = Settings {
+            input_columns: Some(vec!["note".into(), "amount".into(), "tx".into(), "type".into(), "client".into()]),
+            ..test_settings()
+        };
+
+        let accounts = parse_bytes(b"note,amount,tx,type,client\nfirst,10.0,1,deposit,3\n,,1,dispute,3\n", &settings)
+            .expect("Failed to parse input");
+
+        assert_eq!(accounts[&3].funds_held.to_string(), "10");
+    }
+
+    #[test]
+    fn test_input_columns_require_type_client_and_tx() {
+        let settings = Settings {
+            input_columns: Some(vec!["type".into(), "client".into(), "amount".into()]),
+            ..test_settings()
+        };
+
+        let result = parse_bytes(b"type,client,amount\n", &settings);
+
+        assert!(matches!(result, Err(Error::MissingInputColumn("tx"))));
+    }
+
+    #[test]
+    fn test_type_aliases() {
+        let settings = Settings {
+            type_aliases: HashMap::from([("credit".to_string(), "deposit".to_string())]),
+            ..test_settings()
+        };
+        let invalid = Settings {
+            type_aliases: HashMap::from([("credit".to_string(), "bonus".to_string())]),
+            ..test_settings()
+        };
+
+        let accounts = parse_bytes(b"type,client,tx,amount\ncredit,1,1,10.0\ndeposit,1,2,1.0\n", &settings)
+            .expect("Failed to parse input");
+
+        assert_eq!(accounts[&1].funds_available.to_string(), "11");
+        assert!(matches!(parse_bytes(b"type,client,tx,amount\n", &invalid), Err(Error::InvalidTypeAlias(alias)) if alias == "credit"));
+    }
+
     #[test]
     fn test_process_csv_missing_file() {
-        let buffer_capacity = 8192;
-        let result = parse_csv("nonexistent.csv", buffer_capacity);
+        let result = parse_csv("nonexistent.csv", &test_settings());
 
         assert!(result.is_err(), "Should fail when file doesn't exist");
     }
 
+    #[test]
+    fn test_process_csv_logs_run_summary() {
+        test_logger();
+        let path = write_fixture("log_summary.csv", "type,client,tx,amount\ndeposit,1,1,5.0\ndeposit,2,2,5.0\n");
+
+        parse_csv(&path, &test_settings()).expect("Failed to process CSV");
+
+        assert!(logged(log::Level::Info, &format!("Processing transactions from {path}")));
+        assert!(logged(log::Level::Info, &format!("Processed 2 records from {path} into 2 accounts")));
+    }
+
+    #[test]
+    fn test_process_csv_merged_by_transaction_id() {
+        let first = write_fixture("merge_first.csv", "type,client,tx,amount\ndeposit,1,1,10.0\ndispute,1,3,\n");
+        let second = write_fixture("merge_second.csv", "type,client,tx,amount\ndeposit,1,2,5.0\ndeposit,1,3,7.0\n");
+        let paths = [first.as_str(), second.as_str()];
+
+        // Concatenated, the dispute of tx 3 arrives before its deposit
+        let concatenated = parse_csv_files(&paths, &test_settings());
+        assert!(matches!(concatenated, Err(Error::NoTransaction(3, _))));
+
+        let accounts = parse_csv_merged(&paths, &test_settings()).expect("Failed to merge CSVs");
+        let account = accounts.get(&1).expect("Client 1 should exist");
+        assert_eq!(account.funds_available.to_string(), "15");
+        assert_eq!(account.funds_held.to_string(), "7");
+    }
+
+    #[test]
+    fn test_process_csv_merged_skips_invalid_row() {
+        let first = write_fixture(
+            "merge_invalid_first.csv",
+            "type,client,tx,amount\ndeposit,1,1,1.0\nbogus,1,2,1.0\ndeposit,1,4,100.0\ndeposit,1,6,1000.0\n",
+        );
+        let second = write_fixture("merge_invalid_second.csv", "type,client,tx,amount\ndeposit,2,3,5.0\n");
+        let settings = Settings {
+            skip_invalid_rows: true,
+            ..test_settings()
+        };
+        let mut engine = Engine::new(&settings);
+
+        engine.process_merged(&[first.as_str(), second.as_str()]).expect("Failed to merge CSVs");
+
+        assert_eq!(engine.stats().records, 5);
+        assert_eq!(engine.stats().errors, 1);
+        assert_eq!(engine.accounts()[&1].funds_available.to_string(), "1101");
+        assert_eq!(engine.accounts()[&2].funds_available.to_string(), "5");
+    }
+
+    #[test]
+    fn test_process_csv_files_appends() {
+        let first = write_fixture("append_first.csv", "type,client,tx,amount\ndeposit,1,1,10.0\n");
+        let second = write_fixture("append_second.csv", "type,client,tx,amount\nwithdrawal,1,2,4.0\ndeposit,2,3,1.0\n");
+
+        let accounts = parse_csv_files(&[first.as_str(), second.as_str()], &test_settings())
+            .expect("Failed to process CSVs");
+
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts.get(&1).expect("Client 1 should exist").funds_available.to_string(), "6");
+    }
+
+    #[test]
+    fn test_process_csv_dispute_requires_available() {
+        let path = write_fixture(
+            "dispute_requires_available.csv",
+            "type,client,tx,amount\ndeposit,1,1,100.0\nwithdrawal,1,2,80.0\ndispute,1,1,\n",
+        );
+        let settings = Settings {
+            dispute_requires_available: true,
+            ..test_settings()
+        };
+
+        let lenient = parse_csv(&path, &test_settings()).expect("Failed to process CSV");
+        let strict = parse_csv(&path, &settings);
+
+        assert_eq!(lenient.get(&1).expect("Client 1 should exist").funds_available.to_string(), "-80");
+        assert!(matches!(strict, Err(Error::InsufficientAvailableForDispute(1, _))));
+    }
+
+    #[test]
+    fn test_process_csv_timeout() {
+        let mut contents = String::from("type,client,tx,amount\n");
+        for tx in 1..=100_000 {
+            contents.push_str(&format!("deposit,{},{tx},1.0\n", tx % 100));
+        }
+        let path = write_fixture("timeout.csv", &contents);
+        let settings = Settings {
+            max_duration_ms: Some(0),
+            ..test_settings()
+        };
+
+        let result = parse_csv(&path, &settings);
+
+        assert!(matches!(result, Err(Error::Timeout(0))), "Expected timeout, got {:?}", result.err());
+    }
+
+    #[test]
+    fn test_process_csv_within_deadline() {
+        let settings = Settings {
+            max_duration_ms: Some(60_000),
+            ..test_settings()
+        };
+
+        let result = parse_csv("tests/fixtures/test_transactions.csv", &settings);
+
+        assert!(result.is_ok(), "Failed to process CSV: {:?}", result.err());
+    }
+
+    #[test]
+    fn test_process_csv_zero_tx_id_accepted_by_default() {
+        let path = write_fixture("zero_tx_default.csv", "type,client,tx,amount\ndeposit,1,0,5.0\n");
+
+        let accounts = parse_csv(&path, &test_settings()).expect("Failed to process CSV");
+
+        assert_eq!(accounts.get(&1).expect("Client 1 should exist").funds_available.to_string(), "5");
+    }
+
+    #[test]
+    fn test_process_csv_zero_tx_id_rejected() {
+        let path = write_fixture("zero_tx_rejected.csv", "type,client,tx,amount\ndeposit,1,1,5.0\ndeposit,1,0,5.0\n");
+        let settings = Settings {
+            reject_zero_tx_id: true,
+            ..test_settings()
+        };
+
+        let result = parse_csv(&path, &settings);
+
+        assert!(matches!(result.as_ref().map_err(Error::unlocated), Err(Error::InvalidTransactionId(_))), "{:?}", result.err());
+    }
+
+    #[test]
+    fn test_process_csv_duplicate_header() {
+        let path = write_fixture("duplicate_header.csv", "type,client,tx,amount,amount\ndeposit,1,1,5.0,6.0\n");
+
+        let result = parse_csv(&path, &test_settings());
+
+        assert!(matches!(result, Err(Error::DuplicateHeader(name)) if name == "amount"));
+    }
+
+    #[test]
+    fn test_whitespace_only_type_is_missing() {
+        let untrimmed = Settings {
+            trim: TrimMode::None,
+            ..test_settings()
+        };
+
+        assert!(matches!(parse_transaction_type(b"   ", 3), Err(Error::MissingTransactionType(3))));
+        assert!(matches!(parse_transaction_type(b"bogus", 3), Err(Error::UnknownTransactionType(3))));
+        for settings in [test_settings(), untrimmed] {
+            let result = parse_bytes(b"type,client,tx,amount\n ,1,1,100.0\n", &settings);
+            assert!(matches!(result.as_ref().map_err(Error::unlocated), Err(Error::MissingTransactionType(_))));
+        }
+    }
+
+    #[test]
+    fn test_enforce_header() {
+        let settings = Settings {
+            enforce_header: true,
+            ..test_settings()
+        };
+
+        let correct = parse_bytes(b"type, client, tx, amount\ndeposit,1,1,5.0\n", &settings);
+        let wrong = parse_bytes(b"type,client,amount,tx\ndeposit,1,5.0,1\n", &settings);
+        let extra = parse_bytes(b"type,client,tx,amount,note\ndeposit,1,1,5.0,x\n", &settings);
+
+        assert_eq!(correct.expect("Expected header should be accepted")[&1].funds_available.to_string(), "5");
+        assert!(matches!(wrong, Err(Error::UnexpectedHeader(header)) if header == "type,client,amount,tx"));
+        assert!(matches!(extra, Err(Error::UnexpectedHeader(_))));
+    }
+
     #[test]
     fn test_trim_ascii() {
         assert_eq!(trim_ascii(b"  hello  "), b"hello");
@@ -217,22 +1158,759 @@ mod tests {
         assert!(matches!(parse_transaction_type(b"dispute", 1), Ok(TransactionType::Dispute)));
         assert!(matches!(parse_transaction_type(b"resolve", 1), Ok(TransactionType::Resolve)));
         assert!(matches!(parse_transaction_type(b"chargeback", 1), Ok(TransactionType::Chargeback)));
+        assert!(matches!(parse_transaction_type(b"freeze", 1), Ok(TransactionType::Freeze)));
+        assert!(matches!(parse_transaction_type(b"unfreeze", 1), Ok(TransactionType::Unfreeze)));
+        assert!(matches!(parse_transaction_type(b"close", 1), Ok(TransactionType::Close)));
+        assert!(matches!(parse_transaction_type(b"adjust", 1), Ok(TransactionType::Adjust)));
         assert!(matches!(parse_transaction_type(b"invalid", 1), Err(Error::UnknownTransactionType(1))));
         assert!(matches!(parse_transaction_type(b"  deposit  ", 1), Ok(TransactionType::Deposit)));
     }
 
+    #[test]
+    fn test_parse_transaction_type_near_misses() {
+        let types = [
+            ("deposit", TransactionType::Deposit),
+            ("withdrawal", TransactionType::Withdrawal),
+            ("dispute", TransactionType::Dispute),
+            ("resolve", TransactionType::Resolve),
+            ("chargeback", TransactionType::Chargeback),
+            ("freeze", TransactionType::Freeze),
+            ("unfreeze", TransactionType::Unfreeze),
+            ("close", TransactionType::Close),
+            ("transfer", TransactionType::Transfer),
+            ("adjust", TransactionType::Adjust),
+        ];
+        for (name, kind) in types {
+            assert_eq!(parse_transaction_type(name.as_bytes(), 1).ok(), Some(kind), "{name}");
+            let near_misses = [
+                name[..name.len() - 1].to_string(),
+                format!("{name}s"),
+                name.to_uppercase(),
+                format!("x{}", &name[1..]),
+            ];
+            for near_miss in near_misses {
+                assert!(
+                    matches!(parse_transaction_type(near_miss.as_bytes(), 1), Err(Error::UnknownTransactionType(1))),
+                    "{near_miss}"
+                );
+            }
+        }
+        for near_miss in ["d", "deposute", "dispost", "chargebacks", "closeback", "c", "depositdispute"] {
+            assert!(
+                matches!(parse_transaction_type(near_miss.as_bytes(), 1), Err(Error::UnknownTransactionType(1))),
+                "{near_miss}"
+            );
+        }
+    }
+
     #[test]
     fn test_parse_mu_u32_1e4() {
+        let settings = Settings::default();
+
         // Valid amounts
-        assert!(parse_scaled_value(b"100.0", 1).unwrap().is_some());
-        assert!(parse_scaled_value(b"0.1234", 1).unwrap().is_some());
-        assert!(parse_scaled_value(b"  50.25  ", 1).unwrap().is_some());
+        assert!(parse_scaled_value(b"100.0", 1, &settings).unwrap().is_some());
+        assert!(parse_scaled_value(b"0.1234", 1, &settings).unwrap().is_some());
+        assert!(parse_scaled_value(b"  50.25  ", 1, &settings).unwrap().is_some());
 
         // Empty amount
-        assert!(parse_scaled_value(b"", 1).unwrap().is_none());
-        assert!(parse_scaled_value(b"   ", 1).unwrap().is_none());
+        assert!(parse_scaled_value(b"", 1, &settings).unwrap().is_none());
+        assert!(parse_scaled_value(b"   ", 1, &settings).unwrap().is_none());
 
         // Negative amount should error
-        assert!(matches!(parse_scaled_value(b"-100.0", 1), Err(Error::NegativeAmount(1))));
+        assert!(matches!(parse_scaled_value(b"-100.0", 1, &settings), Err(Error::NegativeAmount(1))));
+    }
+
+    /// Mantissa of `integer.fraction` at scale 4, `None` when the fraction exceeds the scale.
+    fn expected_mantissa(integer: u64, fraction: &str) -> Option<i64> {
+        if fraction.len() > 4 {
+            return None;
+        }
+        let padded = format!("{fraction:0<4}");
+        Some(integer as i64 * 10_000 + padded.parse::<i64>().expect("Fraction digits should parse"))
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_scaled_value_scale_rule(integer in 0u64..=922_337_203_685_476, fraction in "[0-9]{0,8}") {
+            let raw = if fraction.is_empty() { integer.to_string() } else { format!("{integer}.{fraction}") };
+
+            let parsed = parse_scaled_value(raw.as_bytes(), 1, &test_settings());
+
+            match expected_mantissa(integer, &fraction) {
+                Some(mantissa) => {
+                    let amount = parsed.expect("Amount within scale should parse").expect("Amount should be present");
+                    prop_assert_eq!(amount.mantissa(), mantissa);
+                    prop_assert_eq!(amount.to_string().parse::<Amount>().expect("Output should parse"), amount);
+                }
+                // Large values with excess digits overflow while scaling, before precision is checked
+                None => prop_assert!(
+                    matches!(parsed, Err(Error::Parse(ParseError::Precision | ParseError::Overflow))),
+                    "{:?}",
+                    parsed
+                ),
+            }
+            let negative = format!("-{}", raw);
+            prop_assert!(matches!(parse_scaled_value(negative.as_bytes(), 1, &test_settings()), Err(Error::NegativeAmount(1))));
+        }
+
+        #[test]
+        fn test_amount_from_str_sign_symmetric(integer in 0u64..=922_337_203_685_476, fraction in "[0-9]{0,8}") {
+            let raw = if fraction.is_empty() { integer.to_string() } else { format!("{integer}.{fraction}") };
+
+            let positive = raw.parse::<Amount>();
+            let negative = format!("-{raw}").parse::<Amount>();
+
+            match expected_mantissa(integer, &fraction) {
+                Some(mantissa) => {
+                    prop_assert_eq!(positive.map(|amount| amount.mantissa()), Ok(mantissa));
+                    let negative = negative.expect("Negative amount within scale should parse");
+                    prop_assert_eq!(negative.mantissa(), -mantissa);
+                    prop_assert_eq!(negative.to_string().parse::<Amount>(), Ok(negative));
+                }
+                None => {
+                    prop_assert!(positive.is_err());
+                    prop_assert_eq!(positive, negative);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_amount_matches_from_str() {
+        let inputs: [&[u8]; 20] = [
+            b"0",
+            b"1",
+            b"007.5",
+            b"100.0",
+            b"0.1234",
+            b"123.4567",
+            b"1.00000",
+            b"1.23456",
+            b"922337203685477.5807",
+            b"922337203685477.5808",
+            b"9223372036854775807",
+            b"+5",
+            b".5",
+            b"5.",
+            b".",
+            b"1.2.3",
+            b"1,5",
+            b"1e2",
+            b" 1",
+            b"",
+        ];
+
+        for input in inputs {
+            let expected = from_utf8(input).ok().and_then(|raw| raw.parse::<Amount>().ok());
+            assert_eq!(parse_amount(input).ok(), expected, "{}", String::from_utf8_lossy(input));
+        }
+    }
+
+    #[test]
+    fn test_parse_scaled_value_rejects_long_field() {
+        let settings = Settings {
+            accept_scientific_notation: true,
+            ..test_settings()
+        };
+        let digits = "9".repeat(1000);
+        let input = format!("type,client,tx,amount\ndeposit,1,1,{digits}\n");
+
+        assert!(matches!(parse_scaled_value(digits.as_bytes(), 1, &settings), Err(Error::AmountTooLong(1))));
+        assert!(matches!(parse_bytes(input.as_bytes(), &settings).as_ref().map_err(Error::unlocated), Err(Error::AmountTooLong(_))));
+        let longest = format!("1{}", "0".repeat(settings.max_amount_len - 1));
+        assert!(matches!(parse_scaled_value(longest.as_bytes(), 1, &settings), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_parse_scaled_value_decimal_comma() {
+        let settings = Settings {
+            decimal_separator: ',',
+            ..Default::default()
+        };
+
+        let amount = parse_scaled_value(b"100,50", 1, &settings).unwrap();
+
+        assert_eq!(amount, Some("100.50".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_scaled_value_currency_symbols() {
+        let dollars = Settings {
+            strip_currency_symbols: vec!["$".to_string(), "€".to_string()],
+            ..Default::default()
+        };
+        let euros = Settings {
+            decimal_separator: ',',
+            ..dollars.clone()
+        };
+
+        assert_eq!(parse_scaled_value(b"$100.50", 1, &dollars).unwrap(), Some(amount!(100.50)));
+        assert_eq!(parse_scaled_value("€100,50".as_bytes(), 1, &euros).unwrap(), Some(amount!(100.50)));
+        assert_eq!(parse_scaled_value("€ 7".as_bytes(), 1, &euros).unwrap(), Some(amount!(7)));
+        assert!(matches!(parse_scaled_value(b"$-5", 1, &dollars), Err(Error::NegativeAmount(1))));
+        assert!(parse_scaled_value(b"$100.50", 1, &Settings::default()).is_err());
+        assert!(matches!(parse_scaled_value(b"$", 1, &dollars), Err(Error::MissingAmount(1))));
+        let strict = Settings {
+            strict_numeric: true,
+            ..dollars.clone()
+        };
+        assert!(matches!(parse_scaled_value(b"$", 1, &strict), Err(Error::MissingAmount(1))));
+        assert!(matches!(parse_scaled_value(b"$ ", 1, &strict), Err(Error::MissingAmount(1))));
+        assert_eq!(parse_scaled_value(b"$100.5", 1, &strict).unwrap(), Some(amount!(100.5)));
+    }
+
+    #[test]
+    fn test_invalid_currency_symbol() {
+        let settings = Settings {
+            decimal_separator: ',',
+            strip_currency_symbols: vec!["€".to_string(), "R,".to_string()],
+            ..test_settings()
+        };
+
+        let result = parse_bytes(b"type,client,tx,amount\n", &settings);
+
+        assert!(matches!(result, Err(Error::InvalidCurrencySymbol(symbol)) if symbol == "R,"));
+    }
+
+    #[test]
+    fn test_process_csv_decimal_comma_with_semicolon_delimiter() {
+        let comma_path = write_fixture("decimal_point.csv", "type,client,tx,amount\ndeposit,1,1,100.50\n");
+        let semicolon_path = write_fixture("decimal_comma.csv", "type;client;tx;amount\ndeposit;1;1;100,50\n");
+        let semicolon_settings = Settings {
+            delimiter: ';',
+            decimal_separator: ',',
+            ..test_settings()
+        };
+
+        let comma_accounts = parse_csv(&comma_path, &test_settings()).expect("Failed to process CSV");
+        let semicolon_accounts = parse_csv(&semicolon_path, &semicolon_settings).expect("Failed to process CSV");
+
+        let expected = comma_accounts.get(&1).expect("Client 1 should exist").funds_available;
+        assert_eq!(expected.to_string(), "100.5");
+        assert_eq!(semicolon_accounts.get(&1).expect("Client 1 should exist").funds_available, expected);
+    }
+
+    #[test]
+    fn test_process_csv_invalid_delimiter() {
+        let settings = Settings {
+            delimiter: '€',
+            ..test_settings()
+        };
+
+        let result = parse_csv("tests/fixtures/test_transactions.csv", &settings);
+
+        assert!(matches!(result, Err(Error::InvalidDelimiter('€'))));
+    }
+
+    #[test]
+    fn test_parse_bytes_comment_lines() {
+        let settings = Settings {
+            comment_char: Some('#'),
+            ..test_settings()
+        };
+        let input = b"# opening balances\ntype,client,tx,amount\ndeposit,1,1,10.0\n# client 1 withdraws\nwithdrawal,1,2,2.5\n#\ndeposit,2,3,1.0\n";
+
+        let accounts = parse_bytes(input, &settings).expect("Comment lines should be skipped");
+
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[&1].funds_available.to_string(), "7.5");
+        assert_eq!(accounts[&2].funds_available.to_string(), "1");
+    }
+
+    #[test]
+    fn test_parse_bytes_comment_lines_keep_line_numbers() {
+        let settings = Settings {
+            comment_char: Some('#'),
+            ..test_settings()
+        };
+        let plain = b"type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,-1.0\n";
+        let commented = b"type,client,tx,amount\ndeposit,1,1,1.0\n# first\n# second\ndeposit,1,2,-1.0\n";
+
+        let negative_amount_line = |input: &[u8]| match parse_bytes(input, &settings).as_ref().map_err(Error::unlocated) {
+            Err(&Error::NegativeAmount(line)) => line,
+            result => panic!("Expected a negative amount error, got {:?}", result.err()),
+        };
+        let plain_line = negative_amount_line(plain);
+        let commented_line = negative_amount_line(commented);
+
+        assert_eq!(commented_line, plain_line + 2);
+    }
+
+    #[test]
+    fn test_parse_bytes_comments_disabled_by_default() {
+        let result = parse_bytes(b"type,client,tx,amount\n# note\ndeposit,1,1,1.0\n", &test_settings());
+
+        assert!(matches!(result.as_ref().map_err(Error::unlocated), Err(Error::UnknownTransactionType(_))), "{:?}", result.err());
+    }
+
+    #[test]
+    fn test_process_csv_invalid_comment_char() {
+        let settings = Settings {
+            comment_char: Some('€'),
+            ..test_settings()
+        };
+
+        let result = parse_bytes(b"type,client,tx,amount\n", &settings);
+
+        assert!(matches!(result, Err(Error::InvalidCommentChar('€'))));
+    }
+
+    #[test]
+    fn test_parse_bytes_balance_overflow() {
+        // Used to panic with "attempt to add with overflow" in debug builds.
+        let input = b"type,client,tx,amount\ndeposit,1,1,922337203685477.5807\ndeposit,1,2,1\n";
+
+        let result = parse_bytes(input, &test_settings());
+
+        assert!(matches!(result, Err(Error::BalanceOverflow(2, _))));
+    }
+
+    #[test]
+    fn test_parse_bytes_malformed_input() {
+        let parse = |input: &[u8]| parse_bytes(input, &test_settings());
+
+        for input in [&b""[..], b"\xff\xfe"] {
+            let accounts = parse(input).expect("Input without rows should be accepted");
+            assert!(accounts.is_empty());
+        }
+        let invalid_type = parse(b"type,client,tx,amount\n\xff,1,1,1\n");
+        let too_precise = parse(b"type,client,tx,amount\ndeposit,1,1,1.00001\n");
+        let overflow = parse(b"type,client,tx,amount\nwithdrawal,1,1,922337203685477.5807\nwithdrawal,1,2,1\n");
+        let invalid_client = parse(b"type,client,tx,amount\ndeposit,99999999999,1,1\n");
+
+        assert!(matches!(invalid_type.as_ref().map_err(Error::unlocated), Err(Error::UnknownTransactionType(3))));
+        assert!(matches!(too_precise.as_ref().map_err(Error::unlocated), Err(Error::Parse(_))));
+        assert!(matches!(overflow.as_ref().map_err(Error::unlocated), Err(Error::BalanceOverflow(2, 4))));
+        assert!(matches!(invalid_client.as_ref().map_err(Error::unlocated), Err(Error::InvalidClient(3))));
+    }
+
+    #[test]
+    fn test_process_csv_zero_byte_file() {
+        let settings = test_settings();
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .process_files(&["tests/fixtures/empty.csv"])
+            .expect("A zero-byte file should be accepted");
+
+        assert_eq!(engine.stats().records, 0);
+        assert!(engine.accounts().is_empty());
+    }
+
+    #[test]
+    fn test_process_csv_header_only_file() {
+        let settings = test_settings();
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .process_files(&["tests/fixtures/header_only.csv"])
+            .expect("A header-only file should be accepted");
+
+        assert_eq!(engine.stats().records, 0);
+        assert!(engine.accounts().is_empty());
+    }
+
+    #[test]
+    fn test_process_csv_without_trailing_newline() {
+        let settings = test_settings();
+        let mut engine = Engine::new(&settings);
+
+        engine
+            .process_files(&["tests/fixtures/no_trailing_newline.csv"])
+            .expect("Failed to process CSV");
+
+        assert_eq!(engine.stats().records, 3);
+        let account = engine.accounts().get(&1).expect("Client 1 should exist");
+        assert_eq!(account.funds_available.to_string(), "3.5");
+    }
+
+    #[test]
+    fn test_parse_bytes_transfer_dest_client_column() {
+        let settings = Settings {
+            allow_transfers: true,
+            ..test_settings()
+        };
+        let input = b"type,client,tx,amount,dest_client\ndeposit,1,1,10.0,\ntransfer,1,2,2.5,2\n";
+
+        let accounts = parse_bytes(input, &settings).expect("Transfer should be accepted");
+
+        assert_eq!(accounts[&1].funds_available.to_string(), "7.5");
+        assert_eq!(accounts[&2].funds_available.to_string(), "2.5");
+    }
+
+    #[test]
+    fn test_parse_bytes_ignores_trailing_column() {
+        let headerless = Settings {
+            has_headers: false,
+            ..test_settings()
+        };
+        let transfers = Settings {
+            allow_transfers: true,
+            ..test_settings()
+        };
+
+        let accounts = parse_bytes(b"deposit,1,1,1.0,note\n", &headerless).expect("Trailing column should be ignored");
+        let with_header = parse_bytes(b"type,client,tx,amount\ndeposit,1,1,1.0,note\n", &transfers)
+            .expect("Trailing column should be ignored");
+
+        assert_eq!(accounts[&1].funds_available.to_string(), "1");
+        assert_eq!(with_header[&1].funds_available.to_string(), "1");
+    }
+
+    #[test]
+    fn test_parse_bytes_account_accessors() {
+        let input = b"type,client,tx,amount\ndeposit,1,1,10.0\ndeposit,1,2,2.5\ndispute,1,2,\ndeposit,2,3,1.0\ndispute,2,3,\nchargeback,2,3,\n";
+
+        let accounts = parse_bytes(input, &test_settings()).expect("Failed to process CSV");
+
+        let account = accounts.get(&1).expect("Client 1 should exist");
+        assert_eq!(account.available().to_string(), "10");
+        assert_eq!(account.held().to_string(), "2.5");
+        assert!(!account.is_locked());
+        let disputes: Vec<(u64, String)> = account.open_disputes().map(|(tx, held)| (tx, held.to_string())).collect();
+        assert_eq!(disputes, vec![(2, "2.5".to_string())]);
+        assert!(accounts[&2].is_locked());
+        assert_eq!(accounts[&2].open_disputes().count(), 0);
+    }
+
+    #[test]
+    fn test_parse_fixed_width_matches_csv() {
+        let settings = Settings {
+            fixed_width: Some(FixedWidth {
+                kind: (0, 10),
+                client: (10, 16),
+                tx: (16, 26),
+                amount: Some((26, 42)),
+                dest_client: None,
+                currency: None,
+            }),
+            ..test_settings()
+        };
+
+        let fixed_width = parse_fixed_width("tests/fixtures/three_clients.txt", &settings).expect("Failed to process fixed-width input");
+        let csv = parse_csv("tests/fixtures/three_clients.csv", &test_settings()).expect("Failed to process CSV");
+
+        assert_eq!(
+            write_accounts(fixed_width).expect("Failed to write accounts"),
+            write_accounts(csv).expect("Failed to write accounts")
+        );
+        assert!(matches!(parse_fixed_width("tests/fixtures/three_clients.txt", &test_settings()), Err(Error::MissingFixedWidth)));
+    }
+
+    #[test]
+    fn test_parse_csv_with_account_factory() {
+        let settings = test_settings();
+        let factory = |client| {
+            let mut account = Account::new(client);
+            account.funds_available = "100".parse().unwrap();
+            account
+        };
+
+        let accounts = parse_csv_with("tests/fixtures/three_clients.csv", &settings, factory)
+            .expect("Failed to process CSV");
+
+        assert_eq!(accounts.len(), 3);
+        assert_eq!(accounts[&1].funds_available.to_string(), "105");
+        assert_eq!(accounts[&2].funds_available.to_string(), "120");
+        assert_eq!(accounts[&3].client, 3);
+    }
+
+    #[test]
+    fn test_validate_locates_invalid_rows() {
+        let settings = test_settings();
+        let input = std::fs::read("tests/fixtures/broken_midway.csv").expect("Failed to read fixture");
+
+        let errors = validate(&input[..], &settings).expect("Validation should read the whole input");
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].position(), Some((6, Some(90))));
+        assert!(input[..90].ends_with(b"deposit,1,4,abc\n"));
+        assert!(matches!(&errors[0], Error::Located { source, .. } if matches!(**source, Error::Parse(_))));
+        assert!(errors[0].is_row_error());
+    }
+
+    #[test]
+    fn test_parse_bytes_error_position() {
+        let input = b"type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,x,2,1.0\n";
+
+        let Err(err) = parse_bytes(input, &test_settings()) else {
+            panic!("Invalid client should fail");
+        };
+
+        assert!(matches!(err.unlocated(), Error::InvalidClient(_)));
+        assert_eq!(err.position(), Some((err.unlocated().line().expect("Error should have a line"), Some(54))));
+        assert_eq!(input.len(), 54);
+        assert_eq!(err.to_string(), format!("{} (byte 54)", err.unlocated()));
+    }
+
+    #[test]
+    fn test_multi_currency_writes_row_per_client_and_currency() {
+        let settings = Settings {
+            multi_currency: true,
+            ..test_settings()
+        };
+        let input = b"type,client,tx,amount,currency\ndeposit,1,1,10.0,USD\ndeposit,1,2,5.0,EUR\nwithdrawal,1,3,2.5,USD\n";
+        let mut engine = Engine::new(&settings);
+        engine
+            .process(TransactionReader::new(&input[..], &settings).expect("Failed to create reader"))
+            .expect("Failed to process CSV");
+        assert!(engine.accounts().is_empty());
+
+        let mut output = Vec::new();
+        write_currency_accounts_to(engine.into_currency_accounts(), &OutputOptions::default(), &mut output)
+            .expect("Failed to write accounts");
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,currency,available,held,total,locked,status\n1,EUR,5,0,5,false,active\n1,USD,7.5,0,7.5,false,active\n"
+        );
+    }
+
+    #[test]
+    fn test_multi_currency_requires_currency() {
+        let settings = Settings {
+            multi_currency: true,
+            ..test_settings()
+        };
+
+        let result = parse_bytes(b"type,client,tx,amount,currency\ndeposit,1,1,10.0,USD\ndeposit,1,2,5.0,\n", &settings);
+
+        assert!(matches!(result.as_ref().map_err(Error::unlocated), Err(Error::MissingCurrency(4))));
+    }
+
+    #[test]
+    fn test_parse_bytes_client_within_max_client_id() {
+        let settings = Settings {
+            max_client_id: Some(9999),
+            ..test_settings()
+        };
+
+        let accounts = parse_bytes(b"type,client,tx,amount\ndeposit,9999,1,1.0\n", &settings)
+            .expect("Client id within the limit should be accepted");
+
+        assert!(accounts.contains_key(&9999));
+    }
+
+    #[test]
+    fn test_parse_bytes_client_above_max_client_id() {
+        let settings = Settings {
+            max_client_id: Some(9999),
+            ..test_settings()
+        };
+
+        let result = parse_bytes(b"type,client,tx,amount\ndeposit,10000,1,1.0\n", &settings);
+
+        assert!(matches!(result.as_ref().map_err(Error::unlocated), Err(Error::ClientOutOfRange(10000, _))));
+    }
+
+    #[test]
+    fn test_process_csv_max_accounts_exceeded() {
+        let settings = Settings {
+            max_accounts: Some(2),
+            ..test_settings()
+        };
+
+        let result = parse_csv("tests/fixtures/three_clients.csv", &settings);
+
+        assert!(matches!(result, Err(Error::AccountLimitExceeded(3, _, 2))), "{:?}", result.err());
+    }
+
+    #[test]
+    fn test_process_csv_within_max_accounts() {
+        let settings = Settings {
+            max_accounts: Some(3),
+            ..test_settings()
+        };
+
+        let accounts = parse_csv("tests/fixtures/three_clients.csv", &settings).expect("Failed to process CSV");
+
+        assert_eq!(accounts.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_bytes_warns_out_of_order_dispute() {
+        let settings = Settings {
+            warn_out_of_order: true,
+            skip_invalid_rows: true,
+            ..test_settings()
+        };
+        let input = b"type,client,tx,amount\ndeposit,1,7,1.0\ndispute,1,7,\ndispute,1,9,\ndeposit,1,9,1.0\n";
+        test_logger();
+
+        parse_bytes(input, &settings).expect("Failed to parse input");
+
+        assert!(logged(log::Level::Warn, "references transaction 9 beyond"));
+        assert!(!logged(log::Level::Warn, "references transaction 7 beyond"));
+    }
+
+    #[test]
+    fn test_parse_bytes_empty_client() {
+        let result = parse_bytes(b"type,client,tx,amount\ndeposit,,1,1.0\n", &test_settings());
+
+        assert!(matches!(result.as_ref().map_err(Error::unlocated), Err(Error::InvalidClient(_))), "{:?}", result.err());
+    }
+
+    #[test]
+    fn test_parse_bytes_non_numeric_client() {
+        let result = parse_bytes(b"type,client,tx,amount\ndeposit,1a,1,1.0\n", &test_settings());
+
+        assert!(matches!(result.as_ref().map_err(Error::unlocated), Err(Error::InvalidClient(_))), "{:?}", result.err());
+    }
+
+    #[cfg(not(feature = "wide-client"))]
+    #[test]
+    fn test_parse_bytes_client_above_u16_rejected() {
+        let result = parse_bytes(b"type,client,tx,amount\ndeposit,70000,1,1.0\n", &test_settings());
+
+        assert!(matches!(result.as_ref().map_err(Error::unlocated), Err(Error::InvalidClient(_))), "{:?}", result.err());
+    }
+
+    #[cfg(feature = "wide-client")]
+    #[test]
+    fn test_parse_bytes_wide_client_id() {
+        let input = b"type,client,tx,amount\ndeposit,70000,1,1.0\ndeposit,4000000000,2,2.0\ndispute,70000,1,\n";
+
+        let accounts = parse_bytes(input, &test_settings()).expect("Wide client ids should be accepted");
+
+        assert_eq!(accounts[&70000].funds_held.to_string(), "1");
+        assert_eq!(accounts[&4_000_000_000].funds_available.to_string(), "2");
+        let output = crate::writer::write_accounts(accounts).expect("Failed to write accounts");
+        assert!(output.contains("\n70000,0,1,1,false,active\n"), "{output}");
+    }
+
+    #[test]
+    fn test_process_csv_directory() {
+        let dir = std::env::temp_dir().join(format!("kraken_{}_daily", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Failed to create fixture directory");
+        std::fs::write(dir.join("2024-01-02.csv"), "type,client,tx,amount\nwithdrawal,1,2,1.5\n")
+            .expect("Failed to write fixture");
+        std::fs::write(dir.join("2024-01-01.csv"), "type,client,tx,amount\ndeposit,1,1,5.0\n")
+            .expect("Failed to write fixture");
+        std::fs::write(dir.join("notes.txt"), "not a csv").expect("Failed to write fixture");
+        let dir = dir.to_string_lossy().into_owned();
+
+        let inputs = expand_inputs(&[dir]).expect("Failed to list directory");
+        let paths: Vec<&str> = inputs.iter().map(String::as_str).collect();
+        let accounts = parse_csv_files(&paths, &test_settings()).expect("Failed to process CSV");
+
+        assert_eq!(inputs.len(), 2);
+        assert!(inputs[0].ends_with("2024-01-01.csv"));
+        assert!(inputs[1].ends_with("2024-01-02.csv"));
+        assert_eq!(accounts.get(&1).expect("Client 1 should exist").funds_available.to_string(), "3.5");
+    }
+
+    #[test]
+    fn test_parse_bytes_trim_none_rejects_padded_amount() {
+        let input = b"type,client,tx,amount\ndeposit,1,1, 100.0 \n";
+        let untrimmed = Settings {
+            trim: TrimMode::None,
+            ..test_settings()
+        };
+
+        let trimmed_accounts = parse_bytes(input, &test_settings()).expect("Padded amount should be trimmed");
+        let result = parse_bytes(input, &untrimmed);
+
+        assert_eq!(trimmed_accounts.get(&1).expect("Client 1 should exist").funds_available.to_string(), "100");
+        assert!(matches!(result.as_ref().map_err(Error::unlocated), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_parse_bytes_inner_space_rejected_in_any_trim_mode() {
+        let input = b"type,client,tx,amount\ndeposit,1,1,1 00.0\n";
+
+        for trim in [TrimMode::All, TrimMode::None] {
+            let settings = Settings { trim, ..test_settings() };
+
+            assert!(matches!(parse_bytes(input, &settings).as_ref().map_err(Error::unlocated), Err(Error::Parse(_))));
+        }
+    }
+
+    #[test]
+    fn test_parse_scientific_notation() {
+        let settings = Settings {
+            accept_scientific_notation: true,
+            ..test_settings()
+        };
+        let parse = |raw: &[u8]| parse_scaled_value(raw, 1, &settings).map(|amount| amount.map(|amount| amount.to_string()));
+
+        assert_eq!(parse(b"1.5e2").ok().flatten().as_deref(), Some("150"));
+        assert_eq!(parse(b"2.5e-1").ok().flatten().as_deref(), Some("0.25"));
+        assert_eq!(parse(b"0.0012E+3").ok().flatten().as_deref(), Some("1.2"));
+        assert_eq!(parse(b"100.5").ok().flatten().as_deref(), Some("100.5"));
+        assert!(matches!(parse(b"1e-5"), Err(Error::Parse(ParseError::Precision))));
+        assert!(matches!(parse(b"1e999999999"), Err(Error::Parse(ParseError::Overflow))));
+        assert!(matches!(parse(b"e2"), Err(Error::Parse(ParseError::Empty))));
+        assert!(matches!(parse(b"1e99999999999999999999"), Err(Error::Parse(ParseError::Invalid))));
+    }
+
+    #[test]
+    fn test_strict_numeric_rejects_non_canonical_amounts() {
+        let settings = Settings {
+            strict_numeric: true,
+            accept_scientific_notation: true,
+            ..test_settings()
+        };
+        let parse = |raw: &[u8]| parse_scaled_value(raw, 7, &settings).map(|amount| amount.map(|amount| amount.to_string()));
+
+        assert!(matches!(parse(b"+100.0"), Err(Error::NonCanonicalAmount(7))));
+        assert!(matches!(parse(b"007.5"), Err(Error::NonCanonicalAmount(7))));
+        assert!(matches!(parse(b"00"), Err(Error::NonCanonicalAmount(7))));
+        assert!(matches!(parse(b"1.5e+2"), Err(Error::NonCanonicalAmount(7))));
+        assert!(matches!(parse(b"1.5E+2"), Err(Error::NonCanonicalAmount(7))));
+        assert_eq!(parse(b"0.5").ok().flatten().as_deref(), Some("0.5"));
+        assert_eq!(parse(b"0").ok().flatten().as_deref(), Some("0"));
+        assert_eq!(parse(b"100.0").ok().flatten().as_deref(), Some("100"));
+        assert_eq!(parse(b"1.5e2").ok().flatten().as_deref(), Some("150"));
+        assert_eq!(parse(b"2.5e-1").ok().flatten().as_deref(), Some("0.25"));
+    }
+
+    #[test]
+    fn test_non_canonical_amounts_accepted_by_default() {
+        let settings = Settings {
+            accept_scientific_notation: true,
+            ..test_settings()
+        };
+
+        for raw in [&b"+100.0"[..], b"007.5", b"1.5e+2"] {
+            assert!(parse_scaled_value(raw, 1, &settings).is_ok(), "{}", String::from_utf8_lossy(raw));
+        }
+    }
+
+    #[test]
+    fn test_scientific_notation_rejected_by_default() {
+        let result = parse_scaled_value(b"1.5e2", 1, &test_settings());
+
+        assert!(matches!(result, Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_parse_lenient_decimal_format() {
+        let settings = Settings {
+            lenient_decimal_format: true,
+            ..test_settings()
+        };
+        let parse = |raw: &[u8]| parse_scaled_value(raw, 1, &settings).map(|amount| amount.map(|amount| amount.to_string()));
+
+        assert_eq!(parse(b".5").ok().flatten().as_deref(), Some("0.5"));
+        assert_eq!(parse(b"5.").ok().flatten().as_deref(), Some("5"));
+        assert!(matches!(parse(b"."), Err(Error::Parse(_))));
+        assert!(matches!(parse_scaled_value(b".5", 1, &test_settings()), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_parse_bytes_aborts_after_max_errors() {
+        let mut input = String::from("type,client,tx,amount\ndeposit,1,1,1.0\n");
+        for tx in 2..100 {
+            input.push_str(&format!("bogus,1,{tx},1.0\n"));
+        }
+        let settings = Settings {
+            skip_invalid_rows: true,
+            max_errors: Some(10),
+            ..test_settings()
+        };
+
+        let result = parse_bytes(input.as_bytes(), &settings);
+
+        assert!(matches!(result, Err(Error::TooManyErrors(11))));
     }
 }
@@ -1,14 +1,173 @@
+use crate::account::DisputePolicy;
+use crate::{Amount, ClientId};
 use config::{Config, ConfigError, File};
+use log::warn;
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Handling of a deposit or withdrawal that takes the available funds out of range.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowMode {
+    /// Fail with `Error::BalanceOverflow`
+    #[default]
+    Error,
+    /// Clamp the available funds to the largest or smallest amount
+    Saturate,
+    /// Leave the account unchanged with a warning
+    Skip,
+}
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct BufferSettings {
     pub capacity: usize,
 }
 
+/// Which parts of the input have surrounding whitespace removed.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TrimMode {
+    #[default]
+    All,
+    Fields,
+    Headers,
+    None,
+}
+
+impl TrimMode {
+    /// Whether data fields, as opposed to the header row, are trimmed.
+    pub fn trims_fields(self) -> bool {
+        matches!(self, TrimMode::All | TrimMode::Fields)
+    }
+}
+
+impl From<TrimMode> for csv::Trim {
+    fn from(mode: TrimMode) -> Self {
+        match mode {
+            TrimMode::All => csv::Trim::All,
+            TrimMode::Fields => csv::Trim::Fields,
+            TrimMode::Headers => csv::Trim::Headers,
+            TrimMode::None => csv::Trim::None,
+        }
+    }
+}
+
+/// Line ending of the output CSV.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LineTerminator {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl From<LineTerminator> for csv::Terminator {
+    fn from(terminator: LineTerminator) -> Self {
+        match terminator {
+            LineTerminator::Lf => csv::Terminator::Any(b'\n'),
+            LineTerminator::Crlf => csv::Terminator::CRLF,
+        }
+    }
+}
+
+/// Byte spans `[start, end)` of the columns of fixed-width input; `amount`, `dest_client` and
+/// `currency` may be left out.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct FixedWidth {
+    #[serde(rename = "type")]
+    pub kind: (usize, usize),
+    pub client: (usize, usize),
+    pub tx: (usize, usize),
+    pub amount: Option<(usize, usize)>,
+    pub dest_client: Option<(usize, usize)>,
+    pub currency: Option<(usize, usize)>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
 pub struct Settings {
     pub buffer: BufferSettings,
+    /// Processing deadline in milliseconds, unlimited when absent
+    pub max_duration_ms: Option<u64>,
+    /// Field delimiter of the input CSV
+    pub delimiter: char,
+    /// Lines starting with this character are skipped, no comments when absent
+    pub comment_char: Option<char>,
+    /// Decimal separator used in amounts, rewritten to `.` before parsing
+    pub decimal_separator: char,
+    /// Whether the input starts with a header row
+    pub has_headers: bool,
+    /// Names of the input columns in order, `type,client,tx,amount` when absent. Columns with
+    /// other names are ignored; `amount` and `dest_client` may be left out
+    pub input_columns: Option<Vec<String>>,
+    /// Reject input whose header row isn't exactly `input_columns`, or `type,client,tx,amount`
+    pub enforce_header: bool,
+    /// Transaction type names of a feed mapped to the standard ones, e.g. `credit` to `deposit`
+    pub type_aliases: HashMap<String, String>,
+    /// Read input files as fixed-width records with these column spans instead of CSV
+    pub fixed_width: Option<FixedWidth>,
+    /// Reject transaction id `0`, which upstream systems often use as a sentinel
+    pub reject_zero_tx_id: bool,
+    /// Shorthand for `dispute_policy = "strict"`, kept for existing configurations
+    pub dispute_requires_available: bool,
+    /// Handling of disputes whose amount exceeds the available funds
+    pub dispute_policy: DisputePolicy,
+    /// Handling of deposits and withdrawals overflowing the available funds
+    pub on_overflow: OverflowMode,
+    /// Ordered subset of output columns, all default columns when absent
+    pub output_columns: Option<Vec<String>>,
+    /// Treat an empty deposit or withdrawal amount as zero instead of an error
+    pub empty_amount_as_zero: bool,
+    /// Accept administrative `freeze`, `unfreeze`, `close` and `adjust` transactions
+    pub allow_admin_ops: bool,
+    /// Highest accepted client id, any client id when absent
+    pub max_client_id: Option<ClientId>,
+    /// Most accounts kept in memory, a transaction for a further client aborts processing; unlimited when absent
+    pub max_accounts: Option<usize>,
+    /// Whitespace trimming of headers and fields; untrimmed amounts with spaces are rejected
+    pub trim: TrimMode,
+    /// Largest amount accepted in a single deposit or withdrawal, unlimited when absent
+    pub max_transaction_amount: Option<Amount>,
+    /// Longest amount field in bytes, longer ones are rejected before parsing
+    pub max_amount_len: usize,
+    /// Accept amounts in scientific notation such as `1.5e2`
+    pub accept_scientific_notation: bool,
+    /// Accept amounts without integer or fraction digits, such as `.5` and `5.`
+    pub lenient_decimal_format: bool,
+    /// Currency symbols removed from the start of amounts, such as `$` in `$100.50`
+    pub strip_currency_symbols: Vec<String>,
+    /// Reject amounts with a leading `+`, leading zeros or a `+` signed exponent
+    pub strict_numeric: bool,
+    /// Fail on a dispute, resolve or chargeback for a locked or closed account instead of
+    /// skipping it with a warning
+    pub reject_disputes_on_locked: bool,
+    /// Deprecated predecessor of `reject_disputes_on_locked`, kept for existing configurations:
+    /// `false` rejects like `reject_disputes_on_locked = true`, `true` skips like the default
+    pub ignore_chargeback_on_locked: Option<bool>,
+    /// Skip rows that fail to parse or apply with a warning instead of failing
+    pub skip_invalid_rows: bool,
+    /// Abort once more than this many invalid rows were skipped, unlimited when absent
+    pub max_errors: Option<u64>,
+    /// Accept `transfer` transactions moving funds to the client in the `dest_client` column
+    pub allow_transfers: bool,
+    /// Track balances per client and currency, read from the `currency` column
+    pub multi_currency: bool,
+    /// Reject disputes, resolves and chargebacks of a client without any earlier transaction
+    pub require_prior_activity: bool,
+    /// Skip a dispute of a transaction that is already disputed instead of failing with `NoTransaction`
+    pub idempotent_dispute: bool,
+    /// Warn about disputes referencing a transaction id above every deposit and withdrawal seen so far
+    pub warn_out_of_order: bool,
+    /// Line ending of the output CSV
+    pub output_terminator: LineTerminator,
+    /// Flush the output after this many accounts, only at the end when absent
+    pub flush_every: Option<usize>,
+    /// Fewest decimal places written for output amounts, padded with trailing zeros
+    pub min_decimals: usize,
+    /// Most decimal places written for output amounts, at most 4; longer amounts are rounded
+    pub max_decimals: usize,
 }
 
 impl Settings {
@@ -17,12 +176,35 @@ impl Settings {
             .add_source(File::with_name("Settings"))
             .build()?;
 
-        settings.try_deserialize()
+        let settings: Self = settings.try_deserialize()?;
+        if settings.ignore_chargeback_on_locked.is_some() {
+            warn!("ignore_chargeback_on_locked is deprecated, use reject_disputes_on_locked instead");
+        }
+        Ok(settings)
     }
 
     pub fn buffer_capacity(&self) -> usize {
         self.buffer.capacity
     }
+
+    /// The configured dispute policy, `Strict` whenever `dispute_requires_available` is set.
+    pub fn effective_dispute_policy(&self) -> DisputePolicy {
+        if self.dispute_requires_available {
+            DisputePolicy::Strict
+        } else {
+            self.dispute_policy
+        }
+    }
+
+    /// Whether disputes, resolves and chargebacks for a locked account fail, either through
+    /// `reject_disputes_on_locked` or the deprecated `ignore_chargeback_on_locked = false`.
+    pub fn rejects_disputes_on_locked(&self) -> bool {
+        self.reject_disputes_on_locked || self.ignore_chargeback_on_locked == Some(false)
+    }
+
+    pub fn max_duration(&self) -> Option<Duration> {
+        self.max_duration_ms.map(Duration::from_millis)
+    }
 }
 
 impl Default for Settings {
@@ -31,6 +213,44 @@ impl Default for Settings {
             buffer: BufferSettings {
                 capacity: 32 * 1024 * 1024, // 32 MB default
             },
+            max_duration_ms: None,
+            delimiter: ',',
+            comment_char: None,
+            decimal_separator: '.',
+            has_headers: true,
+            input_columns: None,
+            enforce_header: false,
+            type_aliases: HashMap::new(),
+            fixed_width: None,
+            reject_zero_tx_id: false,
+            dispute_requires_available: false,
+            dispute_policy: DisputePolicy::AllowNegative,
+            on_overflow: OverflowMode::Error,
+            output_columns: None,
+            empty_amount_as_zero: false,
+            allow_admin_ops: false,
+            max_client_id: None,
+            max_accounts: None,
+            trim: TrimMode::All,
+            max_transaction_amount: None,
+            max_amount_len: 32,
+            accept_scientific_notation: false,
+            lenient_decimal_format: false,
+            strip_currency_symbols: Vec::new(),
+            strict_numeric: false,
+            reject_disputes_on_locked: false,
+            ignore_chargeback_on_locked: None,
+            skip_invalid_rows: false,
+            max_errors: None,
+            allow_transfers: false,
+            multi_currency: false,
+            require_prior_activity: false,
+            idempotent_dispute: false,
+            warn_out_of_order: false,
+            output_terminator: LineTerminator::Lf,
+            flush_every: None,
+            min_decimals: 0,
+            max_decimals: 4,
         }
     }
 }
@@ -0,0 +1,35 @@
+//! Counters and gauges reported through the `metrics` crate when the `metrics` feature is
+//! enabled, and no-ops otherwise.
+
+use crate::account::Account;
+use crate::reader::TransactionType;
+use crate::ClientId;
+use std::collections::HashMap;
+
+/// Counts an applied transaction in `transactions_total`, labeled by its type.
+#[cfg(feature = "metrics")]
+pub(crate) fn transaction_applied(kind: TransactionType) {
+    metrics::counter!("transactions_total", "type" => kind.as_str()).increment(1);
+}
+
+/// Counts a row that failed to parse in `parse_errors_total`.
+#[cfg(feature = "metrics")]
+pub(crate) fn parse_error() {
+    metrics::counter!("parse_errors_total").increment(1);
+}
+
+/// Sets `accounts_locked` to the number of currently locked accounts.
+#[cfg(feature = "metrics")]
+pub(crate) fn accounts_locked(accounts: &HashMap<ClientId, Account>) {
+    let locked = accounts.values().filter(|account| account.locked).count();
+    metrics::gauge!("accounts_locked").set(locked as f64);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn transaction_applied(_kind: TransactionType) {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn parse_error() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn accounts_locked(_accounts: &HashMap<ClientId, Account>) {}
@@ -0,0 +1,1193 @@
+use crate::account::{Account, AccountStatus};
+use crate::error::Error;
+use crate::prelude::*;
+use crate::settings::LineTerminator;
+use crate::{Amount, ClientId};
+use csv::WriterBuilder;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::io;
+use std::thread;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Client,
+    Currency,
+    Available,
+    Held,
+    Total,
+    Locked,
+    Status,
+    TransactionCount,
+    DisputedCount,
+    Checksum,
+    /// Number of accounts, only filled in on the totals row
+    Accounts,
+    /// Number of locked accounts, only filled in on the totals row
+    LockedAccounts,
+}
+
+impl Column {
+    const DEFAULT: [Column; 6] = [
+        Column::Client,
+        Column::Available,
+        Column::Held,
+        Column::Total,
+        Column::Locked,
+        Column::Status,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Client => "client",
+            Column::Currency => "currency",
+            Column::Available => "available",
+            Column::Held => "held",
+            Column::Total => "total",
+            Column::Locked => "locked",
+            Column::Status => "status",
+            Column::TransactionCount => "transaction_count",
+            Column::DisputedCount => "disputed_count",
+            Column::Checksum => "checksum",
+            Column::Accounts => "accounts",
+            Column::LockedAccounts => "locked_accounts",
+        }
+    }
+
+    /// Whether the column holds numbers, which tables align to the right.
+    fn is_numeric(self) -> bool {
+        !matches!(self, Column::Currency | Column::Locked | Column::Status | Column::Checksum)
+    }
+}
+
+impl std::str::FromStr for Column {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "client" => Ok(Column::Client),
+            "currency" => Ok(Column::Currency),
+            "available" => Ok(Column::Available),
+            "held" => Ok(Column::Held),
+            "total" => Ok(Column::Total),
+            "locked" => Ok(Column::Locked),
+            "status" => Ok(Column::Status),
+            "transaction_count" => Ok(Column::TransactionCount),
+            "disputed_count" => Ok(Column::DisputedCount),
+            "checksum" => Ok(Column::Checksum),
+            "accounts" => Ok(Column::Accounts),
+            "locked_accounts" => Ok(Column::LockedAccounts),
+            _ => Err(Error::UnknownColumn(name.to_string())),
+        }
+    }
+}
+
+/// Parses configured output column names, rejecting unknown ones.
+pub fn parse_columns(names: &[String]) -> Result<Vec<Column>> {
+    names.iter().map(|name| name.trim().parse()).collect()
+}
+
+/// Range of decimal places written for amounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decimals {
+    /// Trailing zeros are added up to this many decimals
+    pub min: usize,
+    /// Amounts with more decimals are rounded half away from zero
+    pub max: usize,
+}
+
+impl Decimals {
+    /// Fails unless `min <= max` and `max` is within the four decimals of `Amount`.
+    pub fn new(min: usize, max: usize) -> Result<Self> {
+        if min > max || max > 4 {
+            return Err(Error::InvalidDecimals(min, max));
+        }
+        Ok(Decimals { min, max })
+    }
+}
+
+impl Default for Decimals {
+    /// Insignificant trailing zeros omitted, all four decimals kept.
+    fn default() -> Self {
+        Decimals { min: 0, max: 4 }
+    }
+}
+
+/// Formats an amount with at least `decimals.min` and at most `decimals.max` decimal places,
+/// e.g. `100.50` for `100.5` with two to four decimals.
+pub fn format_amount(amount: Amount, decimals: Decimals) -> String {
+    let mut formatted = amount.round(decimals.max as i32).to_string();
+    let fraction = formatted.split_once('.').map_or(0, |(_, fraction)| fraction.len());
+    if fraction < decimals.min {
+        if fraction == 0 {
+            formatted.push('.');
+        }
+        formatted.push_str(&"0".repeat(decimals.min - fraction));
+    }
+    formatted
+}
+
+/// Guards against a formatting bug writing more decimals than `Amount` holds.
+fn within_scale(formatted: String) -> Result<String> {
+    match formatted.split_once('.') {
+        Some((_, fraction)) if fraction.len() > 4 => Err(Error::PrecisionLeak(formatted)),
+        _ => Ok(formatted),
+    }
+}
+
+/// Order of the account rows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    /// Ascending client id
+    #[default]
+    Client,
+    /// Descending total, ties in ascending client id
+    Balance,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OutputOptions {
+    /// Add a checksum column over each account's final state
+    pub with_checksum: bool,
+    /// Add a column with the number of transactions applied to each account
+    pub with_transaction_count: bool,
+    /// Add a column with the number of open disputes of each account
+    pub with_disputed_count: bool,
+    /// Explicit, ordered output columns replacing the defaults and the `with_*` columns
+    pub columns: Option<Vec<Column>>,
+    /// Fail instead of writing an account whose total is below zero
+    pub deny_negative_total: bool,
+    /// Append a row summing up all accounts
+    pub with_totals: bool,
+    /// Line ending written after every row
+    pub terminator: LineTerminator,
+    /// Write amounts as integers in units of 0.0001 instead of decimals
+    pub minor_units: bool,
+    /// Decimal places of amounts, unless written in minor units
+    pub decimals: Decimals,
+    /// Flush the output after this many accounts, only at the end when absent
+    pub flush_every: Option<usize>,
+    /// Render an aligned text table for reading in a terminal instead of CSV
+    pub table: bool,
+    /// Add a currency column after the client, for accounts tracked per currency
+    pub with_currency: bool,
+    /// Order of the account rows
+    pub sort_by: SortBy,
+}
+
+impl OutputOptions {
+    /// The columns to emit, in order.
+    pub fn columns(&self) -> Vec<Column> {
+        if let Some(columns) = &self.columns {
+            return columns.clone();
+        }
+        let mut columns = Column::DEFAULT.to_vec();
+        if self.with_currency {
+            columns.insert(1, Column::Currency);
+        }
+        if self.with_transaction_count {
+            columns.push(Column::TransactionCount);
+        }
+        if self.with_disputed_count {
+            columns.push(Column::DisputedCount);
+        }
+        if self.with_checksum {
+            columns.push(Column::Checksum);
+        }
+        if self.with_totals {
+            columns.extend([Column::Accounts, Column::LockedAccounts]);
+        }
+        columns
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AccountRecord {
+    pub client: ClientId,
+    pub currency: Option<String>,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub locked: bool,
+    pub status: AccountStatus,
+    pub transaction_count: Option<u64>,
+    pub disputed_count: Option<usize>,
+    pub checksum: Option<String>,
+}
+
+impl AccountRecord {
+    /// SHA-256 over the canonical `client,available,held,locked` fields, hex encoded.
+    pub fn checksum(&self) -> String {
+        let canonical = format!("{},{},{},{}", self.client, self.available, self.held, self.locked);
+        hex(&Sha256::digest(canonical.as_bytes()))
+    }
+
+    /// Reads a record back from an output line written with `columns`.
+    pub fn parse(line: &str, columns: &[Column]) -> Result<Self> {
+        let invalid = || Error::InvalidRecord(line.to_string());
+        let amount = |field: &str| {
+            field
+                .parse::<Amount>()
+                .map(|_| field.to_string())
+                .map_err(|_| invalid())
+        };
+        let fields: Vec<&str> = line.trim_end_matches(['\r', '\n']).split(',').collect();
+        if fields.len() != columns.len() {
+            return Err(invalid());
+        }
+        let mut record = AccountRecord {
+            client: 0,
+            currency: None,
+            available: String::new(),
+            held: String::new(),
+            total: String::new(),
+            locked: false,
+            status: AccountStatus::Active,
+            transaction_count: None,
+            disputed_count: None,
+            checksum: None,
+        };
+        for (&column, &field) in columns.iter().zip(&fields) {
+            match column {
+                Column::Client => record.client = field.parse().map_err(|_| invalid())?,
+                Column::Currency => record.currency = Some(field.to_string()),
+                Column::Available => record.available = amount(field)?,
+                Column::Held => record.held = amount(field)?,
+                Column::Total => record.total = amount(field)?,
+                Column::Locked => record.locked = field.parse().map_err(|_| invalid())?,
+                Column::Status => {
+                    record.status = match field {
+                        "active" => AccountStatus::Active,
+                        "frozen" => AccountStatus::Frozen,
+                        "closed" => AccountStatus::Closed,
+                        _ => return Err(invalid()),
+                    }
+                }
+                Column::TransactionCount => record.transaction_count = Some(field.parse().map_err(|_| invalid())?),
+                Column::DisputedCount => record.disputed_count = Some(field.parse().map_err(|_| invalid())?),
+                Column::Checksum => record.checksum = Some(field.to_string()),
+                Column::Accounts | Column::LockedAccounts => {}
+            }
+        }
+        Ok(record)
+    }
+
+    /// Formatted value of a column, empty when the column wasn't filled in.
+    pub fn field(&self, column: Column) -> Cow<'_, str> {
+        match column {
+            Column::Client => Cow::Owned(self.client.to_string()),
+            Column::Currency => Cow::Borrowed(self.currency.as_deref().unwrap_or_default()),
+            Column::Available => Cow::Borrowed(&self.available),
+            Column::Held => Cow::Borrowed(&self.held),
+            Column::Total => Cow::Borrowed(&self.total),
+            Column::Locked => Cow::Borrowed(if self.locked { "true" } else { "false" }),
+            Column::Status => Cow::Borrowed(self.status.as_str()),
+            Column::TransactionCount => self
+                .transaction_count
+                .map_or(Cow::Borrowed(""), |count| Cow::Owned(count.to_string())),
+            Column::DisputedCount => self
+                .disputed_count
+                .map_or(Cow::Borrowed(""), |count| Cow::Owned(count.to_string())),
+            Column::Checksum => Cow::Borrowed(self.checksum.as_deref().unwrap_or_default()),
+            Column::Accounts | Column::LockedAccounts => Cow::Borrowed(""),
+        }
+    }
+}
+
+impl AccountRecord {
+    /// The record of an account with its amounts formatted to `decimals`.
+    pub fn with_decimals(account: Account, decimals: Decimals) -> Result<Self> {
+        AccountRecord::of(&account, decimals)
+    }
+
+    fn of(account: &Account, decimals: Decimals) -> Result<Self> {
+        let total = total(account)?;
+        Ok(AccountRecord {
+            client: account.client,
+            currency: None,
+            available: within_scale(format_amount(account.funds_available, decimals))?,
+            held: within_scale(format_amount(account.funds_held, decimals))?,
+            total: within_scale(format_amount(total, decimals))?,
+            locked: account.locked,
+            status: account.status(),
+            transaction_count: None,
+            disputed_count: None,
+            checksum: None,
+        })
+    }
+}
+
+impl TryFrom<Account> for AccountRecord {
+    type Error = Error;
+
+    fn try_from(account: Account) -> Result<Self> {
+        AccountRecord::with_decimals(account, Decimals::default())
+    }
+}
+
+/// Sums over all accounts, written as a final row labeled `totals`.
+#[derive(Debug, Default, PartialEq)]
+pub struct TotalsRecord {
+    pub accounts: usize,
+    pub locked: usize,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub transaction_count: u64,
+    pub disputed_count: usize,
+}
+
+impl TotalsRecord {
+    pub fn from_accounts<'a>(accounts: impl IntoIterator<Item = &'a Account>) -> Result<Self> {
+        let mut totals = TotalsRecord::default();
+        for account in accounts {
+            totals.accounts += 1;
+            totals.locked += usize::from(account.locked);
+            totals.available = totals
+                .available
+                .checked_add(account.funds_available)
+                .ok_or(Error::TotalsOverflow)?;
+            totals.held = totals.held.checked_add(account.funds_held).ok_or(Error::TotalsOverflow)?;
+            totals.transaction_count += account.transaction_count();
+            totals.disputed_count += account.disputed_count();
+        }
+        totals.total = totals.available.checked_add(totals.held).ok_or(Error::TotalsOverflow)?;
+        Ok(totals)
+    }
+
+    /// Formatted value of a column. The locked and status columns stay empty, the counts
+    /// go into the accounts and locked_accounts columns.
+    pub fn field(&self, column: Column, options: &OutputOptions) -> String {
+        let amount = |amount: Amount| {
+            if options.minor_units {
+                minor_units(amount)
+            } else {
+                format_amount(amount, options.decimals)
+            }
+        };
+        match column {
+            Column::Client => "totals".to_string(),
+            Column::Currency => String::new(),
+            Column::Available => amount(self.available),
+            Column::Held => amount(self.held),
+            Column::Total => amount(self.total),
+            Column::Locked | Column::Status | Column::Checksum => String::new(),
+            Column::TransactionCount => self.transaction_count.to_string(),
+            Column::DisputedCount => self.disputed_count.to_string(),
+            Column::Accounts => self.accounts.to_string(),
+            Column::LockedAccounts => self.locked.to_string(),
+        }
+    }
+}
+
+impl TryFrom<&str> for AccountRecord {
+    type Error = Error;
+
+    /// Parses a line written with the default columns.
+    fn try_from(line: &str) -> Result<Self> {
+        AccountRecord::parse(line, &Column::DEFAULT)
+    }
+}
+
+/// The scaled integer behind an amount, e.g. `1005000` for `100.5`.
+fn minor_units(amount: Amount) -> String {
+    amount.mantissa().to_string()
+}
+
+fn total(account: &Account) -> Result<Amount> {
+    account
+        .funds_held
+        .checked_add(account.funds_available)
+        .ok_or(Error::TotalOverflow(account.client))
+}
+
+/// Converts the accounts into output records ordered by client id.
+pub fn into_records(accounts: HashMap<ClientId, Account>) -> Result<Vec<AccountRecord>> {
+    records(&accounts)
+}
+
+/// Like `into_records`, filling in the optional columns selected by `options` and ordering
+/// the records by `options.sort_by`.
+pub fn into_records_with(accounts: HashMap<ClientId, Account>, options: &OutputOptions) -> Result<Vec<AccountRecord>> {
+    records_with(&accounts, options)
+}
+
+/// Like `into_records`, leaving the accounts with the caller.
+pub fn records(accounts: &HashMap<ClientId, Account>) -> Result<Vec<AccountRecord>> {
+    records_with(accounts, &OutputOptions::default())
+}
+
+/// Like `into_records_with`, leaving the accounts with the caller.
+pub fn records_with(accounts: &HashMap<ClientId, Account>, options: &OutputOptions) -> Result<Vec<AccountRecord>> {
+    let columns = options.columns();
+    let with_transaction_count = columns.contains(&Column::TransactionCount);
+    let with_disputed_count = columns.contains(&Column::DisputedCount);
+    let with_checksum = columns.contains(&Column::Checksum);
+    let mut records = accounts
+        .values()
+        .map(|account| {
+            let transaction_count = account.transaction_count();
+            let disputed_count = account.disputed_count();
+            let total = total(account)?;
+            if options.deny_negative_total && total.is_neg() {
+                return Err(Error::NegativeTotal(account.client));
+            }
+            let (available, held) = (account.funds_available, account.funds_held);
+            let mut record = AccountRecord::of(account, options.decimals)?;
+            if options.minor_units {
+                record.available = minor_units(available);
+                record.held = minor_units(held);
+                record.total = minor_units(total);
+            }
+            if with_transaction_count {
+                record.transaction_count = Some(transaction_count);
+            }
+            if with_disputed_count {
+                record.disputed_count = Some(disputed_count);
+            }
+            if with_checksum {
+                record.checksum = Some(record.checksum());
+            }
+            Ok((total, record))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    records.sort_by(|(_, a), (_, b)| record_order(a, b));
+    if options.sort_by == SortBy::Balance {
+        // Stable, so accounts with equal totals stay in client order
+        records.sort_by(|(a, _), (b, _)| b.cmp(a));
+    }
+    Ok(records.into_iter().map(|(_, record)| record).collect())
+}
+
+/// Puts records in output order: by client, then currency, with records without a currency
+/// first. The sort is stable, so records equal in both keep their order.
+pub fn sort_records(records: &mut [AccountRecord]) {
+    records.sort_by(record_order);
+}
+
+fn record_order(a: &AccountRecord, b: &AccountRecord) -> Ordering {
+    a.client.cmp(&b.client).then_with(|| a.currency.cmp(&b.currency))
+}
+
+pub fn write_accounts(accounts: HashMap<ClientId, Account>) -> Result<String> {
+    write_accounts_with(accounts, &OutputOptions::default())
+}
+
+pub fn write_accounts_with(accounts: HashMap<ClientId, Account>, options: &OutputOptions) -> Result<String> {
+    let mut output = Vec::new();
+    write_accounts_to(accounts, options, &mut output)?;
+    String::from_utf8(output).map_err(|err| err.utf8_error().into())
+}
+
+/// Streams the output to `out`, flushing after every `options.flush_every` accounts so a
+/// slow consumer sees rows as they are produced. All records are built before the first
+/// row is written, so a failing account never leaves partial output behind.
+pub fn write_accounts_to<W: io::Write>(accounts: HashMap<ClientId, Account>, options: &OutputOptions, out: W) -> Result<()> {
+    write_borrowed_accounts_to(&accounts, options, out)
+}
+
+/// Like `write_accounts_to`, leaving the accounts with the caller, so snapshots can be
+/// written in the middle of processing without copying the ledger.
+pub fn write_borrowed_accounts_to<W: io::Write>(accounts: &HashMap<ClientId, Account>, options: &OutputOptions, out: W) -> Result<()> {
+    let totals = if options.with_totals {
+        Some(TotalsRecord::from_accounts(accounts.values())?)
+    } else {
+        None
+    };
+    let records = records_with(accounts, options)?;
+    write_records(&records, totals.as_ref(), options, out)
+}
+
+/// Like `write_accounts_to` for accounts tracked per currency, writing one row per client and
+/// currency ordered by client, then currency, whatever `options.sort_by`. No totals row is written since amounts in
+/// different currencies don't add up.
+pub fn write_currency_accounts_to<W: io::Write>(
+    ledgers: BTreeMap<String, HashMap<ClientId, Account>>,
+    options: &OutputOptions,
+    out: W,
+) -> Result<()> {
+    write_borrowed_currency_accounts_to(&ledgers, options, out)
+}
+
+/// Like `write_currency_accounts_to`, leaving the accounts with the caller.
+pub fn write_borrowed_currency_accounts_to<W: io::Write>(
+    ledgers: &BTreeMap<String, HashMap<ClientId, Account>>,
+    options: &OutputOptions,
+    out: W,
+) -> Result<()> {
+    let options = OutputOptions {
+        with_currency: true,
+        ..options.clone()
+    };
+    let mut records = Vec::new();
+    for (currency, accounts) in ledgers {
+        for mut record in records_with(accounts, &options)? {
+            record.currency = Some(currency.clone());
+            records.push(record);
+        }
+    }
+    sort_records(&mut records);
+    write_records(&records, None, &options, out)
+}
+
+fn write_records<W: io::Write>(records: &[AccountRecord], totals: Option<&TotalsRecord>, options: &OutputOptions, out: W) -> Result<()> {
+    let columns = options.columns();
+    if options.table {
+        return write_table(&columns, records, totals, options, RetryWouldBlock(out));
+    }
+    let mut writer = WriterBuilder::new()
+        .terminator(options.terminator.into())
+        .from_writer(RetryWouldBlock(out));
+    writer.write_record(columns.iter().map(|column| column.name()))?;
+    for (index, record) in records.iter().enumerate() {
+        writer.write_record(columns.iter().map(|&column| record.field(column).into_owned()))?;
+        if let Some(flush_every) = options.flush_every
+            && (index + 1).is_multiple_of(flush_every.max(1))
+        {
+            writer.flush()?;
+        }
+    }
+    if let Some(totals) = totals {
+        writer.write_record(columns.iter().map(|&column| totals.field(column, options)))?;
+    }
+    writer.into_inner().map_err(|err| Error::from(err.into_error()))?;
+    Ok(())
+}
+
+/// Writes every open dispute as a `client,tx,held` row, ordered by client and transaction id.
+pub fn write_dispute_detail<W: io::Write>(accounts: &HashMap<ClientId, Account>, options: &OutputOptions, out: W) -> Result<()> {
+    let mut disputes: Vec<(ClientId, u64, Amount)> = accounts
+        .values()
+        .flat_map(|account| account.open_disputes().map(|(tx, held)| (account.client, tx, held)))
+        .collect();
+    disputes.sort_unstable_by_key(|&(client, tx, _)| (client, tx));
+    let mut writer = WriterBuilder::new()
+        .terminator(options.terminator.into())
+        .from_writer(RetryWouldBlock(out));
+    writer.write_record(["client", "tx", "held"])?;
+    for (client, tx, held) in disputes {
+        let held = if options.minor_units { minor_units(held) } else { format_amount(held, options.decimals) };
+        writer.write_record([client.to_string(), tx.to_string(), held])?;
+    }
+    writer.into_inner().map_err(|err| Error::from(err.into_error()))?;
+    Ok(())
+}
+
+/// Writes the records as a table with a border around the header, every column padded
+/// to its widest value and numbers aligned to the right.
+fn write_table<W: io::Write>(
+    columns: &[Column],
+    records: &[AccountRecord],
+    totals: Option<&TotalsRecord>,
+    options: &OutputOptions,
+    mut out: W,
+) -> Result<()> {
+    let mut rows: Vec<Vec<String>> = records
+        .iter()
+        .map(|record| columns.iter().map(|&column| record.field(column).into_owned()).collect())
+        .collect();
+    if let Some(totals) = totals {
+        rows.push(columns.iter().map(|&column| totals.field(column, options)).collect());
+    }
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| rows.iter().map(|row| row[index].len()).fold(column.name().len(), usize::max))
+        .collect();
+    let border = widths.iter().fold(String::from("+"), |mut border, &width| {
+        border.push_str(&"-".repeat(width + 2));
+        border.push('+');
+        border
+    });
+    let line = |cells: &mut dyn Iterator<Item = &str>| {
+        let mut line = String::from("|");
+        for ((cell, &width), column) in cells.zip(&widths).zip(columns) {
+            if column.is_numeric() {
+                let _ = write!(line, " {cell:>width$} |");
+            } else {
+                let _ = write!(line, " {cell:<width$} |");
+            }
+        }
+        line
+    };
+    writeln!(out, "{border}")?;
+    writeln!(out, "{}", line(&mut columns.iter().map(|column| column.name())))?;
+    writeln!(out, "{border}")?;
+    for (index, row) in rows.iter().enumerate() {
+        if totals.is_some() && index + 1 == rows.len() {
+            writeln!(out, "{border}")?;
+        }
+        writeln!(out, "{}", line(&mut row.iter().map(String::as_str)))?;
+    }
+    writeln!(out, "{border}")?;
+    out.flush()?;
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+/// Passes the output through to `W` while feeding every written byte to a SHA-256 hasher,
+/// so two runs can be compared by a single digest of their complete output.
+pub struct DigestWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: io::Write> DigestWriter<W> {
+    pub fn new(inner: W) -> Self {
+        DigestWriter { inner, hasher: Sha256::new() }
+    }
+
+    /// Hex encoded digest of everything written so far.
+    pub fn digest(&self) -> String {
+        hex(&self.hasher.clone().finalize())
+    }
+}
+
+impl<W: io::Write> io::Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Retries writes that fail with `WouldBlock`, so a non-blocking consumer applies
+/// backpressure instead of aborting the output.
+struct RetryWouldBlock<W>(W);
+
+impl<W: io::Write> io::Write for RetryWouldBlock<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            match self.0.write(buf) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::yield_now(),
+                result => return result,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        loop {
+            match self.0.flush() {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::yield_now(),
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::DisputePolicy;
+    use crate::amount;
+
+    #[test]
+    fn test_write_accounts_status_column() {
+        let mut account = Account::new(2);
+        account.deposit(1, amount!(10.0)).expect("Deposit should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+        account.chargeback(1).expect("Chargeback should succeed");
+        let accounts = HashMap::from([(2, account)]);
+
+        let output = write_accounts(accounts).expect("Failed to write accounts");
+
+        assert_eq!(output, "client,available,held,total,locked,status\n2,0,0,0,true,frozen\n");
+    }
+
+    fn checksummed_output(amount: &str) -> String {
+        let mut account = Account::new(1);
+        account.deposit(1, amount.parse().unwrap()).expect("Deposit should succeed");
+        let accounts = HashMap::from([(1, account)]);
+        let options = OutputOptions {
+            with_checksum: true,
+            ..Default::default()
+        };
+        write_accounts_with(accounts, &options).expect("Failed to write accounts")
+    }
+
+    fn output_digest(amount: &str) -> (String, String) {
+        let mut account = Account::new(1);
+        account.deposit(1, amount.parse().unwrap()).expect("Deposit should succeed");
+        let mut out = DigestWriter::new(Vec::new());
+        write_accounts_to(HashMap::from([(1, account)]), &OutputOptions::default(), &mut out).expect("Failed to write accounts");
+        let digest = out.digest();
+        (String::from_utf8(out.inner).expect("Output should be UTF-8"), digest)
+    }
+
+    #[test]
+    fn test_output_digest_covers_written_bytes() {
+        let (output, digest) = output_digest("10.0");
+
+        assert_eq!(digest, hex(&Sha256::digest(output.as_bytes())));
+        assert_eq!(output_digest("10.0").1, digest);
+        assert_ne!(output_digest("10.5").1, digest);
+    }
+
+    #[test]
+    fn test_write_accounts_checksum_column() {
+        let output = checksummed_output("10.0");
+
+        let mut lines = output.lines();
+        assert_eq!(lines.next(), Some("client,available,held,total,locked,status,checksum"));
+        let checksum = lines.next().and_then(|line| line.rsplit(',').next()).expect("Missing row");
+        assert_eq!(checksum.len(), 64);
+        assert!(checksum.bytes().all(|byte| byte.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn test_write_accounts_checksum_is_stable() {
+        assert_eq!(checksummed_output("10.0"), checksummed_output("10.0"));
+        assert_ne!(checksummed_output("10.0"), checksummed_output("10.0001"));
+    }
+
+    #[test]
+    fn test_write_accounts_transaction_count_column() {
+        let mut account = Account::new(1);
+        account.deposit(1, amount!(10.0)).expect("Deposit should succeed");
+        account.deposit(2, amount!(5.0)).expect("Deposit should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+        let accounts = HashMap::from([(1, account)]);
+        let options = OutputOptions {
+            with_transaction_count: true,
+            ..Default::default()
+        };
+
+        let output = write_accounts_with(accounts, &options).expect("Failed to write accounts");
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,status,transaction_count\n1,5,10,15,false,active,3\n"
+        );
+    }
+
+    #[test]
+    fn test_write_accounts_disputed_count_column() {
+        let mut account = Account::new(1);
+        account.deposit(1, amount!(10.0)).expect("Deposit should succeed");
+        account.deposit(2, amount!(5.0)).expect("Deposit should succeed");
+        account.deposit(3, amount!(1.0)).expect("Deposit should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+        account.dispute(2).expect("Dispute should succeed");
+        let accounts = HashMap::from([(1, account)]);
+        let options = OutputOptions {
+            with_disputed_count: true,
+            ..Default::default()
+        };
+
+        let output = write_accounts_with(accounts, &options).expect("Failed to write accounts");
+
+        assert_eq!(output, "client,available,held,total,locked,status,disputed_count\n1,1,15,16,false,active,2\n");
+    }
+
+    #[test]
+    fn test_write_accounts_selected_columns() {
+        let mut account = Account::new(1);
+        account.deposit(1, amount!(10.0)).expect("Deposit should succeed");
+        account.deposit(2, amount!(5.0)).expect("Deposit should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+        let accounts = HashMap::from([(1, account)]);
+        let columns = parse_columns(&["client".to_string(), "available".to_string()]).unwrap();
+        let options = OutputOptions {
+            columns: Some(columns),
+            ..Default::default()
+        };
+
+        let output = write_accounts_with(accounts, &options).expect("Failed to write accounts");
+
+        assert_eq!(output, "client,available\n1,5\n");
+    }
+
+    #[test]
+    fn test_parse_columns_rejects_unknown() {
+        let result = parse_columns(&["client".to_string(), "balance".to_string()]);
+
+        assert!(matches!(result, Err(Error::UnknownColumn(name)) if name == "balance"));
+    }
+
+    #[test]
+    fn test_write_accounts_empty() {
+        let output = write_accounts(HashMap::new()).expect("Failed to write accounts");
+
+        assert_eq!(output, "client,available,held,total,locked,status\n");
+    }
+
+    #[test]
+    fn test_into_records_sorted_by_client() {
+        let mut accounts = HashMap::new();
+        for client in [7, 3, 5] {
+            let mut account = Account::new(client);
+            account.deposit(1, amount!(10.0)).expect("Deposit should succeed");
+            account.deposit(2, amount!(2.5)).expect("Deposit should succeed");
+            account.dispute(2).expect("Dispute should succeed");
+            accounts.insert(client, account);
+        }
+
+        let records = into_records(accounts).expect("Failed to convert accounts");
+
+        let clients: Vec<ClientId> = records.iter().map(|record| record.client).collect();
+        assert_eq!(clients, vec![3, 5, 7]);
+        let record = &records[0];
+        assert_eq!(record.available, "10");
+        assert_eq!(record.held, "2.5");
+        assert_eq!(record.total, "12.5");
+        assert!(!record.locked);
+        assert_eq!(record.status, AccountStatus::Active);
+    }
+
+    #[test]
+    fn test_records_leave_accounts_with_caller() {
+        let settings = crate::settings::Settings::default();
+        let accounts = crate::reader::parse_csv("tests/fixtures/three_clients.csv", &settings)
+            .expect("Failed to process CSV");
+        let options = OutputOptions {
+            with_transaction_count: true,
+            ..Default::default()
+        };
+
+        let borrowed = records_with(&accounts, &options).expect("Failed to convert accounts");
+
+        assert_eq!(borrowed, into_records_with(accounts, &options).expect("Failed to convert accounts"));
+    }
+
+    #[test]
+    fn test_into_records_sorted_by_balance() {
+        let settings = crate::settings::Settings::default();
+        let accounts = crate::reader::parse_csv("tests/fixtures/three_clients.csv", &settings)
+            .expect("Failed to process CSV");
+        let mut tied = Account::new(4);
+        tied.deposit(1, amount!(20.0)).expect("Deposit should succeed");
+        let mut with_tie = accounts.clone();
+        with_tie.insert(4, tied);
+        let options = OutputOptions {
+            sort_by: SortBy::Balance,
+            ..Default::default()
+        };
+
+        let records = into_records_with(accounts, &options).expect("Failed to convert accounts");
+        let tied_records = into_records_with(with_tie, &options).expect("Failed to convert accounts");
+
+        let order = |records: &[AccountRecord]| -> Vec<(ClientId, String)> {
+            records.iter().map(|record| (record.client, record.total.clone())).collect()
+        };
+        assert_eq!(
+            order(&records),
+            vec![(3, "30".to_string()), (2, "20".to_string()), (1, "5".to_string())]
+        );
+        let clients: Vec<ClientId> = tied_records.iter().map(|record| record.client).collect();
+        assert_eq!(clients, vec![3, 2, 4, 1]);
+    }
+
+    #[test]
+    fn test_sort_records_by_client_then_currency() {
+        let record = |client: ClientId, currency: Option<&str>| AccountRecord {
+            currency: currency.map(str::to_string),
+            ..AccountRecord::try_from(Account::new(client)).expect("Failed to convert account")
+        };
+        let mut records = vec![
+            record(2, Some("USD")),
+            record(1, Some("USD")),
+            record(2, Some("EUR")),
+            record(1, None),
+            record(1, Some("EUR")),
+        ];
+
+        sort_records(&mut records);
+
+        let order: Vec<(ClientId, Option<&str>)> =
+            records.iter().map(|record| (record.client, record.currency.as_deref())).collect();
+        assert_eq!(
+            order,
+            vec![(1, None), (1, Some("EUR")), (1, Some("USD")), (2, Some("EUR")), (2, Some("USD"))]
+        );
+    }
+
+    #[test]
+    fn test_into_records_total_overflow() {
+        let mut account = Account::new(1);
+        account.deposit(1, amount!(1.0)).expect("Deposit should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+        account.deposit(2, crate::Amount::MAX).expect("Deposit should succeed");
+        let accounts = HashMap::from([(1, account)]);
+
+        let result = into_records(accounts);
+
+        assert!(matches!(result, Err(Error::TotalOverflow(1))));
+    }
+
+    #[test]
+    fn test_into_records_deny_negative_total() {
+        let mut account = Account::new(3);
+        account.deposit(1, amount!(1.0)).expect("Deposit should succeed");
+        account.withdraw(2, amount!(2.5)).expect("Withdrawal should succeed");
+        let options = OutputOptions {
+            deny_negative_total: true,
+            ..Default::default()
+        };
+
+        let allowed = into_records(HashMap::from([(3, Account::new(3))]));
+        let result = write_accounts_with(HashMap::from([(3, account)]), &options);
+
+        assert!(allowed.is_ok());
+        assert!(matches!(result, Err(Error::NegativeTotal(3))));
+    }
+
+    #[test]
+    fn test_write_accounts_with_totals() {
+        let settings = crate::settings::Settings::default();
+        let accounts = crate::reader::parse_csv("tests/fixtures/test_transactions.csv", &settings)
+            .expect("Failed to process CSV");
+        let options = OutputOptions {
+            with_totals: true,
+            ..Default::default()
+        };
+
+        let output = write_accounts_with(accounts, &options).expect("Failed to write accounts");
+
+        assert_eq!(output.lines().last(), Some("totals,75.25,0,75.25,,,2,1"));
+    }
+
+    #[test]
+    fn test_totals_overflow() {
+        let mut first = Account::new(1);
+        first.deposit(1, Amount::MAX).expect("Deposit should succeed");
+        let mut second = Account::new(2);
+        second.deposit(2, Amount::MAX).expect("Deposit should succeed");
+
+        let result = TotalsRecord::from_accounts([&first, &second]);
+
+        assert!(matches!(result, Err(Error::TotalsOverflow)));
+    }
+
+    #[test]
+    fn test_write_accounts_crlf_terminator() {
+        let mut account = Account::new(1);
+        account.deposit(1, amount!(10.0)).expect("Deposit should succeed");
+        let options = OutputOptions {
+            terminator: LineTerminator::Crlf,
+            ..Default::default()
+        };
+
+        let output = write_accounts_with(HashMap::from([(1, account)]), &options).expect("Failed to write accounts");
+
+        assert_eq!(output, "client,available,held,total,locked,status\r\n1,10,0,10,false,active\r\n");
+    }
+
+    fn disputed_accounts() -> HashMap<ClientId, Account> {
+        let mut account = Account::new(4);
+        account.deposit(1, amount!(10.5)).expect("Deposit should succeed");
+        account.dispute(1).expect("Dispute should succeed");
+        HashMap::from([(4, account)])
+    }
+
+    #[test]
+    fn test_account_record_round_trip() {
+        let options = OutputOptions {
+            with_transaction_count: true,
+            with_checksum: true,
+            ..Default::default()
+        };
+
+        let expected = into_records_with(disputed_accounts(), &options).expect("Failed to build records");
+        let output = write_accounts_with(disputed_accounts(), &options).expect("Failed to write accounts");
+        let line = output.lines().nth(1).expect("Output should contain a record");
+        let parsed = AccountRecord::parse(line, &options.columns()).expect("Failed to parse record");
+
+        assert_eq!(parsed, expected[0]);
+        assert_eq!(parsed.held, "10.5");
+        assert_eq!(parsed.transaction_count, Some(2));
+    }
+
+    #[test]
+    fn test_account_record_from_default_line() {
+        let record = AccountRecord::try_from("2,-50,0,-50,true,frozen\n").expect("Failed to parse record");
+
+        assert_eq!(record.client, 2);
+        assert_eq!(record.total, "-50");
+        assert!(record.locked);
+        assert_eq!(record.status, AccountStatus::Frozen);
+        assert!(matches!(AccountRecord::try_from("2,-50,0"), Err(Error::InvalidRecord(_))));
+        assert!(matches!(AccountRecord::try_from("2,abc,0,-50,true,frozen"), Err(Error::InvalidRecord(_))));
+    }
+
+    #[test]
+    fn test_write_accounts_minor_units() {
+        let mut account = Account::new(1);
+        account.deposit(1, amount!(100.5)).expect("Deposit should succeed");
+        account.deposit(2, amount!(0.0001)).expect("Deposit should succeed");
+        account.dispute(2).expect("Dispute should succeed");
+        let options = OutputOptions {
+            minor_units: true,
+            with_totals: true,
+            ..Default::default()
+        };
+
+        let output = write_accounts_with(HashMap::from([(1, account)]), &options).expect("Failed to write accounts");
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,status,accounts,locked_accounts\n1,1005000,1,1005001,false,active,,\ntotals,1005000,1,1005001,,,1,0\n"
+        );
+    }
+
+    /// Records flushes, and refuses every other write with `WouldBlock` like a full pipe.
+    #[derive(Default)]
+    struct SlowConsumer {
+        data: Vec<u8>,
+        flushed_at: Vec<usize>,
+        blocked: bool,
+    }
+
+    impl io::Write for SlowConsumer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.blocked = !self.blocked;
+            if self.blocked {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            // Accept at most a few bytes at a time to exercise partial writes
+            let written = buf.len().min(7);
+            self.data.extend_from_slice(&buf[..written]);
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushed_at.push(self.data.len());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_accounts_to_flushes_periodically() {
+        let accounts = (1..=5)
+            .map(|client| {
+                let mut account = Account::new(client);
+                account.deposit(1, amount!(1.0)).expect("Deposit should succeed");
+                (client, account)
+            })
+            .collect::<HashMap<_, _>>();
+        let options = OutputOptions {
+            flush_every: Some(2),
+            ..Default::default()
+        };
+        let mut consumer = SlowConsumer::default();
+
+        write_accounts_to(accounts, &options, &mut consumer).expect("Failed to write accounts");
+
+        let output = String::from_utf8(consumer.data).expect("Output should be UTF-8");
+        let row_end = |rows: usize| output.split_inclusive('\n').take(rows).map(str::len).sum::<usize>();
+        assert_eq!(output.lines().count(), 6);
+        // After the header and two accounts, after four accounts, and once at the end
+        assert_eq!(consumer.flushed_at, vec![row_end(3), row_end(5), output.len()]);
+    }
+
+    #[test]
+    fn test_write_dispute_detail() {
+        let mut first = Account::new(1);
+        first.deposit(1, amount!(10.0)).expect("Deposit should succeed");
+        first.deposit(2, amount!(2.5)).expect("Deposit should succeed");
+        first.deposit(3, amount!(1.0)).expect("Deposit should succeed");
+        first.dispute(2).expect("Dispute should succeed");
+        let mut second = Account::new(2);
+        second.deposit(4, amount!(4.0)).expect("Deposit should succeed");
+        second.withdraw(5, amount!(3.0)).expect("Withdrawal should succeed");
+        second.dispute_with(4, DisputePolicy::Clamp).expect("Dispute should succeed");
+        let accounts = HashMap::from([(2, second), (1, first)]);
+        let mut output = Vec::new();
+
+        write_dispute_detail(&accounts, &OutputOptions::default(), &mut output).expect("Failed to write dispute detail");
+
+        assert_eq!(String::from_utf8(output).unwrap(), "client,tx,held\n1,2,2.5\n2,4,1\n");
+    }
+
+    #[test]
+    fn test_format_amount_min_decimals() {
+        let decimals = Decimals::new(2, 4).expect("Valid decimals");
+
+        assert_eq!(format_amount(amount!(100.5), decimals), "100.50");
+        assert_eq!(format_amount(amount!(100), decimals), "100.00");
+        assert_eq!(format_amount(amount!(100.1234), decimals), "100.1234");
+        assert_eq!(format_amount(amount!(-0.5), decimals), "-0.50");
+        assert_eq!(format_amount(amount!(0), decimals), "0.00");
+    }
+
+    #[test]
+    fn test_format_amount_max_decimals_rounds() {
+        let decimals = Decimals::new(0, 2).expect("Valid decimals");
+
+        assert_eq!(format_amount(amount!(100.1250), decimals), "100.13");
+        assert_eq!(format_amount(amount!(100.1249), decimals), "100.12");
+        assert_eq!(format_amount(amount!(-100.125), decimals), "-100.13");
+        assert_eq!(format_amount(amount!(100.5), decimals), "100.5");
+    }
+
+    #[test]
+    fn test_within_scale() {
+        let mut account = Account::new(1);
+        account.deposit(1, amount!(0.0001)).expect("Deposit should succeed");
+        account.withdraw(2, amount!(12345.6789)).expect("Withdrawal should succeed");
+        let decimals = Decimals::new(4, 4).expect("Valid decimals");
+
+        let record = AccountRecord::with_decimals(account, decimals).expect("Record should be within scale");
+
+        assert_eq!(record.available, "-12345.6788");
+        assert_eq!(within_scale("100".to_string()).ok().as_deref(), Some("100"));
+        assert!(matches!(within_scale("100.00001".to_string()), Err(Error::PrecisionLeak(amount)) if amount == "100.00001"));
+    }
+
+    #[test]
+    fn test_decimals_rejects_invalid_range() {
+        assert!(matches!(Decimals::new(3, 2), Err(Error::InvalidDecimals(3, 2))));
+        assert!(matches!(Decimals::new(0, 5), Err(Error::InvalidDecimals(0, 5))));
+    }
+
+    #[test]
+    fn test_write_accounts_min_decimals() {
+        let mut account = Account::new(1);
+        account.deposit(1, amount!(100.5)).expect("Deposit should succeed");
+        account.deposit(2, amount!(2)).expect("Deposit should succeed");
+        account.dispute(2).expect("Dispute should succeed");
+        let options = OutputOptions {
+            decimals: Decimals::new(2, 4).expect("Valid decimals"),
+            ..Default::default()
+        };
+
+        let output = write_accounts_with(HashMap::from([(1, account)]), &options).expect("Failed to write accounts");
+
+        assert_eq!(output, "client,available,held,total,locked,status\n1,100.50,2.00,102.50,false,active\n");
+    }
+
+    #[test]
+    fn test_write_accounts_table() {
+        let mut accounts = HashMap::new();
+        for (client, amount) in [(12, "1.5"), (3, "100.25")] {
+            let mut account = Account::new(client);
+            account.deposit(1, amount.parse().expect("Failed to parse amount")).expect("Deposit should succeed");
+            accounts.insert(client, account);
+        }
+        let options = OutputOptions {
+            table: true,
+            ..Default::default()
+        };
+
+        let output = write_accounts_with(accounts, &options).expect("Failed to write table");
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[1], "| client | available | held |  total | locked | status |");
+        assert_eq!(lines[3], "|      3 |    100.25 |    0 | 100.25 | false  | active |");
+        assert_eq!(lines[4], "|     12 |       1.5 |    0 |    1.5 | false  | active |");
+        assert!(lines.iter().all(|line| line.len() == lines[0].len()));
+    }
+}
@@ -0,0 +1,252 @@
+use assert_cmd::Command;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+
+fn kraken() -> Command {
+    Command::new(assert_cmd::cargo::cargo_bin!("kraken"))
+}
+
+#[test]
+fn test_output_matches_golden_file() {
+    let expected = fs::read_to_string("tests/fixtures/test_transactions.expected.csv").expect("Failed to read golden file");
+
+    kraken()
+        .arg("tests/fixtures/test_transactions.csv")
+        .assert()
+        .success()
+        .stdout(expected);
+}
+
+#[test]
+fn test_missing_argument_prints_usage() {
+    let output = kraken().output().expect("Failed to run binary");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Missing csv file"));
+    assert!(stderr.contains("Usage: "));
+}
+
+#[test]
+fn test_unknown_option_prints_usage() {
+    let output = kraken()
+        .args(["--bogus", "tests/fixtures/test_transactions.csv"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown option --bogus"));
+}
+
+#[test]
+fn test_missing_file_fails() {
+    kraken()
+        .arg("tests/fixtures/does_not_exist.csv")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("");
+}
+
+#[test]
+fn test_summary_goes_to_stderr() {
+    let expected = fs::read_to_string("tests/fixtures/test_transactions.expected.csv").expect("Failed to read golden file");
+
+    let output = kraken()
+        .args(["--summary", "tests/fixtures/test_transactions.csv"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Summary: records: 9"));
+}
+
+#[test]
+fn test_fail_on_warn() {
+    let expected = "client,available,held,total,locked,status\n1,0,0,0,true,frozen\n";
+
+    kraken().arg("tests/fixtures/locked_deposit.csv").assert().success().stdout(expected);
+    kraken()
+        .args(["--fail-on-warn", "tests/fixtures/locked_deposit.csv"])
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(expected);
+}
+
+#[test]
+fn test_seed_accounts() {
+    kraken()
+        .args(["--seed-accounts", "tests/fixtures/seed_accounts.csv", "tests/fixtures/three_clients.csv"])
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked,status\n1,105,0,105,false,active\n2,5,0,5,true,frozen\n3,30,0,30,false,active\n");
+}
+
+#[test]
+fn test_seeded_held_funds_pass_invariants() {
+    kraken()
+        .args([
+            "--seed-accounts",
+            "tests/fixtures/seed_held.csv",
+            "--check-invariants",
+            "tests/fixtures/three_clients.csv",
+        ])
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked,status\n1,15,5,20,false,active\n2,20,0,20,false,active\n3,30,0,30,false,active\n");
+}
+
+#[test]
+fn test_quiet_leaves_stderr_empty() {
+    let expected = "client,available,held,total,locked,status\n1,0,0,0,true,frozen\n";
+
+    let noisy = kraken()
+        .args(["--summary", "tests/fixtures/locked_deposit.csv"])
+        .output()
+        .expect("Failed to run binary");
+    assert!(!noisy.stderr.is_empty());
+    kraken()
+        .args(["--quiet", "--summary", "tests/fixtures/locked_deposit.csv"])
+        .assert()
+        .success()
+        .stdout(expected)
+        .stderr("");
+}
+
+#[test]
+fn test_output_digest_is_stable_and_tracks_amounts() {
+    let digest = |input: &str| {
+        let output = kraken().args(["--output-digest", input]).output().expect("Failed to run binary");
+        assert!(output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        stderr
+            .lines()
+            .find_map(|line| line.strip_prefix("Output digest: "))
+            .map(str::to_string)
+            .expect("Missing output digest")
+    };
+    let changed = std::env::temp_dir().join(format!("kraken-digest-{}.csv", std::process::id()));
+    let input = fs::read_to_string("tests/fixtures/test_transactions.csv").expect("Failed to read fixture");
+    fs::write(&changed, input.replacen("deposit,1,1,100.0", "deposit,1,1,100.5", 1)).expect("Failed to write input");
+
+    let first = digest("tests/fixtures/test_transactions.csv");
+
+    assert_eq!(first.len(), 64);
+    assert_eq!(digest("tests/fixtures/test_transactions.csv"), first);
+    assert_ne!(digest(changed.to_str().unwrap()), first);
+    fs::remove_file(changed).ok();
+}
+
+#[test]
+fn test_partial_on_error_prints_accounts_before_failure() {
+    kraken().arg("tests/fixtures/broken_midway.csv").assert().failure().code(1).stdout("");
+
+    let output = kraken()
+        .args(["--partial-on-error", "tests/fixtures/broken_midway.csv"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "client,available,held,total,locked,status\n1,7.5,0,7.5,false,active\n2,5,0,5,false,active\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Stopped on line 6"));
+}
+
+#[test]
+fn test_max_hold_ratio_reports_fully_held_account() {
+    let output = kraken()
+        .args(["--max-hold-ratio", "0.5", "tests/fixtures/held_ratio.csv"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Client 1 holds 1.0000 of its total"));
+    assert!(!stderr.contains("Client 2"));
+    kraken()
+        .args(["--max-hold-ratio", "0.5", "--fail-on-warn", "tests/fixtures/held_ratio.csv"])
+        .assert()
+        .failure()
+        .code(1);
+}
+
+#[test]
+fn test_color_left_out_when_stderr_is_not_a_terminal() {
+    let run = |args: &[&str]| {
+        let output = kraken().args(args).output().expect("Failed to run binary");
+        assert_eq!(output.status.code(), Some(1));
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    let user = run(&["--color", "tests/fixtures/broken_midway.csv"]);
+    let system = run(&["--color", "tests/fixtures/missing.csv"]);
+    let plain = run(&["tests/fixtures/broken_midway.csv"]);
+
+    assert!(user.contains("user error: "), "{user}");
+    assert!(system.contains("system error: "), "{system}");
+    assert!(!plain.contains("user error") && !plain.contains("system error"), "{plain}");
+    for stderr in [user, system, plain] {
+        assert!(!stderr.contains('\x1b'), "{stderr:?}");
+    }
+}
+
+#[test]
+fn test_listen_answers_on_connection() {
+    let expected = fs::read_to_string("tests/fixtures/test_transactions.expected.csv").expect("Failed to read golden file");
+    let input = fs::read("tests/fixtures/test_transactions.csv").expect("Failed to read fixture");
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("Failed to find a free port");
+    let child = std::process::Command::new(assert_cmd::cargo::cargo_bin!("kraken"))
+        .args(["--listen", &addr.to_string()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run binary");
+
+    let mut stream = (0..100)
+        .find_map(|_| {
+            TcpStream::connect(addr)
+                .inspect_err(|_| thread::sleep(Duration::from_millis(50)))
+                .ok()
+        })
+        .expect("Failed to connect");
+    stream.write_all(&input).expect("Failed to send transactions");
+    stream.shutdown(Shutdown::Write).expect("Failed to end the input");
+    let mut output = String::new();
+    stream.read_to_string(&mut output).expect("Failed to read accounts");
+
+    assert_eq!(output, expected);
+    let result = child.wait_with_output().expect("Failed to wait for binary");
+    assert!(result.status.success());
+    assert!(result.stdout.is_empty());
+}
+
+#[test]
+fn test_warn_empty() {
+    let header = "client,available,held,total,locked,status\n";
+
+    for fixture in ["tests/fixtures/empty.csv", "tests/fixtures/header_only.csv"] {
+        let quiet = kraken().arg(fixture).output().expect("Failed to run binary");
+        let warned = kraken().args(["--warn-empty", fixture]).output().expect("Failed to run binary");
+
+        assert!(quiet.status.success());
+        assert!(quiet.stderr.is_empty());
+        assert!(warned.status.success());
+        assert_eq!(String::from_utf8_lossy(&warned.stdout), header);
+        assert!(String::from_utf8_lossy(&warned.stderr).contains("no transactions"));
+    }
+    kraken()
+        .args(["--warn-empty", "tests/fixtures/test_transactions.csv"])
+        .assert()
+        .success()
+        .stderr("");
+}